@@ -0,0 +1,259 @@
+//! JSON endpoints for integrations that don't speak the websocket protocol.
+
+use axum::{
+    extract::{Extension, Path},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use axum_auth::AuthBearer;
+use common::{
+    BacklogStory, EstimatedStory, GameAction, GameError, GameEvent, GameId, SelectedStory, StoryId,
+    StoryInfo,
+};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::sync::Arc;
+
+use crate::{apply_to_record, is_client_action, play_action, player_by_token, AppState};
+
+pub(crate) async fn game_snapshot(state: &AppState, game_id: GameId) -> Response {
+    if let Some(record) = state.games.lock().await.get(&game_id) {
+        Json(record.game.clone()).into_response()
+    } else {
+        StatusCode::NOT_FOUND.into_response()
+    }
+}
+
+#[derive(Serialize)]
+pub(crate) struct Stories {
+    backlog: Vec<BacklogStory>,
+    selected: Option<SelectedStory>,
+    estimated: Vec<EstimatedStory>,
+}
+
+pub(crate) async fn list_stories(
+    Path(game_id): Path<GameId>,
+    Extension(state): Extension<Arc<AppState>>,
+) -> Response {
+    let games = state.games.lock().await;
+    let Some(record) = games.get(&game_id) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let game = &record.game;
+    Json(Stories {
+        backlog: game.backlog_stories.values().cloned().collect(),
+        selected: game.selected_story.clone(),
+        estimated: game.estimated_stories.values().cloned().collect(),
+    })
+    .into_response()
+}
+
+pub(crate) async fn add_stories(
+    AuthBearer(token): AuthBearer,
+    Path(game_id): Path<GameId>,
+    Extension(state): Extension<Arc<AppState>>,
+    Json(infos): Json<Vec<StoryInfo>>,
+) -> Response {
+    let stories = infos.into_iter().map(BacklogStory::new).collect::<Vec<_>>();
+    let action = GameAction::StoriesAdded(stories.clone());
+    match play(&state, game_id, &token, action).await {
+        Ok(_) => (StatusCode::CREATED, Json(stories)).into_response(),
+        Err(response) => response,
+    }
+}
+
+// fields missing in the body keep their values, merged under the lock to keep concurrent edits
+pub(crate) async fn update_story(
+    AuthBearer(token): AuthBearer,
+    Path((game_id, story_id)): Path<(GameId, StoryId)>,
+    Extension(state): Extension<Arc<AppState>>,
+    Json(fields): Json<Map<String, Value>>,
+) -> Response {
+    let Some(user_id) = player_by_token(&state, game_id, &token).await else {
+        return StatusCode::UNAUTHORIZED.into_response();
+    };
+    let Some(tx) = state.channels.read().await.get(&game_id).cloned() else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let mut games = state.games.lock().await;
+    let Some(record) = games.get_mut(&game_id) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let Some(story) = record.game.backlog_stories.get(&story_id) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let mut info = serde_json::to_value(&story.info).unwrap();
+    if let Value::Object(info) = &mut info {
+        info.extend(fields);
+    }
+    let info = match serde_json::from_value(info) {
+        Ok(info) => info,
+        Err(err) => return (StatusCode::BAD_REQUEST, err.to_string()).into_response(),
+    };
+    let action = GameAction::StoryUpdated(story_id, info);
+    if let Err(err) = apply_to_record(&state, &tx, record, user_id, action) {
+        return error_response(err);
+    }
+    Json(record.game.backlog_stories[&story_id].clone()).into_response()
+}
+
+pub(crate) async fn remove_story(
+    AuthBearer(token): AuthBearer,
+    Path((game_id, story_id)): Path<(GameId, StoryId)>,
+    Extension(state): Extension<Arc<AppState>>,
+) -> Response {
+    match play(&state, game_id, &token, GameAction::StoryRemoved(story_id)).await {
+        Ok(_) => StatusCode::NO_CONTENT.into_response(),
+        Err(response) => response,
+    }
+}
+
+// replies with what the action changed, nothing if it was a no-op
+pub(crate) async fn post_action(
+    AuthBearer(token): AuthBearer,
+    Path(game_id): Path<GameId>,
+    Extension(state): Extension<Arc<AppState>>,
+    Json(action): Json<GameAction>,
+) -> Response {
+    match play(&state, game_id, &token, action).await {
+        Ok(events) => Json(events).into_response(),
+        Err(response) => response,
+    }
+}
+
+// plays the action as the player the session token was issued to, connected clients get it as well
+async fn play(
+    state: &Arc<AppState>,
+    game_id: GameId,
+    token: &str,
+    action: GameAction,
+) -> Result<Vec<GameEvent>, Response> {
+    let Some(user_id) = player_by_token(state, game_id, token).await else {
+        return Err(StatusCode::UNAUTHORIZED.into_response());
+    };
+    if !is_client_action(&action) {
+        let message = "only the server issues this action";
+        return Err((StatusCode::FORBIDDEN, message).into_response());
+    }
+    let Some(tx) = state.channels.read().await.get(&game_id).cloned() else {
+        return Err(StatusCode::NOT_FOUND.into_response());
+    };
+    play_action(state, &tx, game_id, user_id, action)
+        .await
+        .map_err(error_response)
+}
+
+fn error_response(err: GameError) -> Response {
+    let status = match err {
+        GameError::NotAPlayer
+        | GameError::NotAdmin
+        | GameError::CannotVote
+        | GameError::AdminActive => StatusCode::FORBIDDEN,
+        GameError::UnknownPlayer | GameError::UnknownStory => StatusCode::NOT_FOUND,
+        GameError::LastAdmin
+        | GameError::IndexOutOfRange
+        | GameError::NoStorySelected
+        | GameError::InvalidVote
+        | GameError::TimerTooLong
+        | GameError::VotesNotRevealed
+        | GameError::VotesAlreadyRevealed
+        | GameError::NoVotes
+        | GameError::NoEstimate => StatusCode::CONFLICT,
+    };
+    (status, err.to_string()).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        store::MemoryStore,
+        testing::{call, create_game, test_app},
+    };
+    use axum::{
+        http::{Method, StatusCode},
+        Router,
+    };
+    use common::{BacklogStory, GameEvent, StoryInfo};
+    use serde_json::{json, Value};
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn actions_are_played_as_the_token_holder() {
+        let app = test_app(Arc::new(MemoryStore));
+        let (created, token) = create_game(app.clone()).await;
+        let uri = format!("/api/game/{}/actions", created.id);
+        let action = json!({ "AutoRevealChanged": true });
+
+        let (status, body) =
+            call(app.clone(), Method::POST, &uri, Some(&token), Some(action)).await;
+        assert_eq!(status, StatusCode::OK);
+        let events: Vec<GameEvent> = serde_json::from_slice(&body).unwrap();
+        assert_eq!(events, [GameEvent::AutoRevealChanged(true)]);
+    }
+
+    async fn post(app: Router, uri: &str, token: &str, action: Value) -> StatusCode {
+        let (status, _) = call(app, Method::POST, uri, Some(token), Some(action)).await;
+        status
+    }
+
+    #[tokio::test]
+    async fn rejected_actions_get_an_error_status() {
+        let app = test_app(Arc::new(MemoryStore));
+        let (created, token) = create_game(app.clone()).await;
+        let uri = format!("/api/game/{}/actions", created.id);
+        let wrong_token = uuid::Uuid::new_v4().to_string();
+        let status = post(app.clone(), &uri, &wrong_token, json!("VotesRevealed")).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let status = post(app.clone(), &uri, &token, json!("AdminClaimed")).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let story = BacklogStory::new(StoryInfo::new("Login".to_string()));
+        let status = post(
+            app.clone(),
+            &uri,
+            &token,
+            json!({ "VotingOpened": [story.id, 0] }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let status = post(app.clone(), &uri, &token, json!("VotesRevealed")).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+    }
+
+    #[tokio::test]
+    async fn story_updates_keep_the_missing_fields() {
+        let app = test_app(Arc::new(MemoryStore));
+        let (created, token) = create_game(app.clone()).await;
+        let stories = json!([{ "title": "Login", "key": "POK-1", "labels": ["auth"] }]);
+        let uri = format!("/api/game/{}/stories", created.id);
+        let (status, body) =
+            call(app.clone(), Method::POST, &uri, Some(&token), Some(stories)).await;
+        assert_eq!(status, StatusCode::CREATED);
+        let stories: Vec<BacklogStory> = serde_json::from_slice(&body).unwrap();
+
+        let uri = format!("/api/game/{}/stories/{}", created.id, stories[0].id);
+        let fields = json!({ "title": "Log in" });
+        let (status, body) =
+            call(app.clone(), Method::PATCH, &uri, Some(&token), Some(fields)).await;
+        assert_eq!(status, StatusCode::OK);
+        let story: BacklogStory = serde_json::from_slice(&body).unwrap();
+        assert_eq!(story.info.title, "Log in");
+        assert_eq!(story.info.key.as_deref(), Some("POK-1"));
+        assert_eq!(story.info.labels, ["auth"]);
+
+        let fields = json!({ "title": 1 });
+        let (status, _) = call(app.clone(), Method::PATCH, &uri, Some(&token), Some(fields)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let missing = format!("/api/game/{}/stories/{}", created.id, uuid::Uuid::new_v4());
+        let fields = json!({ "title": "Log out" });
+        let (status, _) = call(
+            app.clone(),
+            Method::PATCH,
+            &missing,
+            Some(&token),
+            Some(fields),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,79 @@
+//! Probes for the orchestrator and the build the server runs.
+
+use axum::{extract::Extension, http::StatusCode, response::IntoResponse, Json};
+use common::{VersionInfo, PROTOCOL_VERSION};
+use std::{io, sync::Arc};
+
+use crate::AppState;
+
+/// The process is up and serving requests.
+#[allow(clippy::unused_async)]
+pub(crate) async fn healthz() -> impl IntoResponse {
+    "ok"
+}
+
+/// The server can take games, which needs the store to be available.
+pub(crate) async fn readyz(Extension(state): Extension<Arc<AppState>>) -> impl IntoResponse {
+    // the file store writes a probe, which would block the runtime
+    let store = state.store.clone();
+    let checked = tokio::task::spawn_blocking(move || store.check())
+        .await
+        .unwrap_or_else(|err| Err(io::Error::new(io::ErrorKind::Other, err)));
+    match checked {
+        Ok(()) => (StatusCode::OK, "ready".to_string()),
+        Err(err) => {
+            tracing::warn!("store isn't available: {:?}", err);
+            let message = format!("store isn't available: {err}");
+            (StatusCode::SERVICE_UNAVAILABLE, message)
+        }
+    }
+}
+
+#[allow(clippy::unused_async)]
+pub(crate) async fn version() -> impl IntoResponse {
+    Json(VersionInfo {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        git_sha: env!("GIT_SHA").to_string(),
+        protocol_version: PROTOCOL_VERSION,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        store::{FileStore, MemoryStore},
+        testing::{get, test_app},
+    };
+    use axum::http::StatusCode;
+    use common::{VersionInfo, PROTOCOL_VERSION};
+    use std::{fs, sync::Arc};
+
+    #[tokio::test]
+    async fn healthz_is_ok() {
+        let (status, body) = get(test_app(Arc::new(MemoryStore)), "/healthz").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, b"ok");
+    }
+
+    #[tokio::test]
+    async fn readyz_follows_the_store() {
+        let dir = std::env::temp_dir().join(format!("readyz-{}", uuid::Uuid::new_v4()));
+        let app = test_app(Arc::new(FileStore::new(&dir).unwrap()));
+        let (status, _) = get(app.clone(), "/readyz").await;
+        assert_eq!(status, StatusCode::OK);
+
+        fs::remove_dir_all(&dir).unwrap();
+        let (status, _) = get(app.clone(), "/readyz").await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[tokio::test]
+    async fn version_reports_the_build() {
+        let (status, body) = get(test_app(Arc::new(MemoryStore)), "/api/version").await;
+        assert_eq!(status, StatusCode::OK);
+        let version: VersionInfo = serde_json::from_slice(&body).unwrap();
+        assert_eq!(version.version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(version.protocol_version, PROTOCOL_VERSION);
+        assert!(!version.git_sha.is_empty());
+    }
+}
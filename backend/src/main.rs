@@ -14,30 +14,112 @@
     clippy::must_use_candidate
 )]
 
+mod api;
+mod health;
+mod metrics;
+mod openapi;
+mod store;
+#[cfg(test)]
+mod testing;
+mod webhooks;
+
 use axum::{
     extract::{
         ws::{Message, WebSocket},
-        Extension, Path, WebSocketUpgrade,
+        Extension, Path, Query, WebSocketUpgrade,
     },
-    http::StatusCode,
+    http::{header, StatusCode},
     response::{IntoResponse, Response},
-    routing::{delete, get, post},
+    routing::{delete, get, patch, post},
     Json, Router,
 };
 use axum_auth::AuthBearer;
-use common::{AppEvent, Game, GameAction, GameId, User, UserId};
-use futures::{sink::SinkExt, stream::StreamExt};
-use std::{collections::HashMap, net::SocketAddr, sync::Arc};
-use tokio::sync::{broadcast, Mutex, RwLock};
+use common::{
+    backlog_csv,
+    issue_import::{self, ImportError, ImportFormat},
+    report::GameSummary,
+    AppEvent, ErrorCode, GameAction, GameCreated, GameError, GameEvent, GameId, NewGame, Presence,
+    RoundTimer, SessionToken, Timestamp, User, UserId, PROTOCOL_VERSION,
+};
+use futures::{
+    sink::SinkExt,
+    stream::{SplitSink, SplitStream, StreamExt},
+};
+use metrics::Metrics;
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use store::{FileStore, GameRecord, GameStore, MemoryStore, StoreWriter};
+use tokio::{
+    sync::{broadcast, broadcast::error::RecvError, mpsc, Mutex, RwLock},
+    time::timeout,
+};
 use tower_http::services::ServeDir;
 use tower_http::trace::{DefaultMakeSpan, TraceLayer};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+use webhooks::{Dispatcher, RetryPolicy};
 
 // Our shared state
-#[derive(Default)]
 struct AppState {
-    games: Mutex<HashMap<GameId, Game>>,
+    games: Mutex<HashMap<GameId, GameRecord>>,
     channels: RwLock<HashMap<GameId, broadcast::Sender<String>>>,
+    store: Arc<dyn GameStore>,
+    /// Writes the changes to the store in the background.
+    writer: StoreWriter,
+    /// How long a disconnected player stays in the game, waiting to reconnect.
+    grace_period: Duration,
+    webhooks: Dispatcher,
+    metrics: Metrics,
+}
+
+impl AppState {
+    // restore games kept by the store, nobody is connected to them yet
+    fn load(
+        store: Arc<dyn GameStore>,
+        grace_period: Duration,
+        webhooks: Dispatcher,
+    ) -> std::io::Result<Self> {
+        let mut games = HashMap::new();
+        let mut channels = HashMap::new();
+        for mut record in store.load()? {
+            let active_players = record.game.to_active_players();
+            for user_id in active_players.keys() {
+                record.apply(*user_id, GameAction::PlayerLeft, now()).ok();
+            }
+            channels.insert(record.game.id, new_channel());
+            games.insert(record.game.id, record);
+        }
+        tracing::debug!("loaded {} games", games.len());
+
+        Ok(AppState {
+            games: Mutex::new(games),
+            channels: RwLock::new(channels),
+            writer: StoreWriter::spawn(store.clone()),
+            store,
+            grace_period,
+            webhooks,
+            metrics: Metrics::new(),
+        })
+    }
+
+    // the record gets written later, only a copy is made while the games are locked
+    fn persist(&self, record: &GameRecord) {
+        self.writer.save(record.clone());
+    }
+}
+
+/// How often the connections get pinged.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+/// How long a connection can stay silent before it's considered dropped.
+const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(45);
+
+fn new_channel() -> broadcast::Sender<String> {
+    let (tx, _rx) = broadcast::channel(100);
+    tx
 }
 
 // Our secret secret
@@ -52,19 +134,42 @@ async fn main() {
         .with(tracing_subscriber::fmt::layer())
         .init();
 
-    let app_state = Arc::<AppState>::default();
+    let store: Arc<dyn GameStore> = match std::env::var("GAMES_DIR") {
+        Ok(dir) => Arc::new(FileStore::new(dir).unwrap()),
+        Err(_) => Arc::new(MemoryStore),
+    };
+    let grace_seconds: u64 = std::env::var("DISCONNECT_GRACE_SECONDS")
+        .unwrap_or_else(|_| "30".to_string())
+        .parse()
+        .unwrap();
+    let grace_period = Duration::from_secs(grace_seconds);
+    // only for webhooks served from the same host or network, like in development
+    let allow_private =
+        std::env::var("WEBHOOK_ALLOW_PRIVATE_ADDRESSES").map_or(false, |value| value == "true");
+    let webhooks = Dispatcher::new(RetryPolicy::default(), allow_private);
+    let app_state = Arc::new(AppState::load(store, grace_period, webhooks).unwrap());
+    resume_timers(&app_state).await;
+    let handoff_minutes: u64 = std::env::var("ADMIN_HANDOFF_MINUTES")
+        .unwrap_or_else(|_| "5".to_string())
+        .parse()
+        .unwrap();
+    // zero minutes disables the automatic hand-off
+    if handoff_minutes > 0 {
+        let timeout = Duration::from_secs(handoff_minutes * 60);
+        tokio::spawn(hand_off_admin(app_state.clone(), timeout));
+    }
+    let ttl_minutes: u64 = std::env::var("GAME_TTL_MINUTES")
+        .unwrap_or_else(|_| "1440".to_string())
+        .parse()
+        .unwrap();
+    // zero minutes keeps the games forever
+    if ttl_minutes > 0 {
+        let ttl = Duration::from_secs(ttl_minutes * 60);
+        let archive = std::env::var("ARCHIVE_EXPIRED_GAMES").map_or(false, |value| value == "true");
+        tokio::spawn(reap_games(app_state.clone(), ttl, archive));
+    }
     let secret = std::env::var("API_SECRET").ok();
-    let secret = Arc::new(AppSecret(secret));
-    let tracing_layer = TraceLayer::new_for_http()
-        .make_span_with(DefaultMakeSpan::default().include_headers(false));
-    let app = Router::new()
-        .nest_service("/assets", ServeDir::new("dist"))
-        .route("/api/internal_state", delete(delete_internal_state))
-        .route("/api/game", post(create_game))
-        .route("/api/game/:game_id", get(ws_handler))
-        .layer(tracing_layer)
-        .layer(Extension(secret))
-        .layer(Extension(app_state));
+    let app = app(app_state, AppSecret(secret));
     let port = std::env::var("PORT")
         .unwrap_or_else(|_| "3000".to_string())
         .parse()
@@ -77,6 +182,50 @@ async fn main() {
         .unwrap();
 }
 
+fn app(app_state: Arc<AppState>, secret: AppSecret) -> Router {
+    let tracing_layer = TraceLayer::new_for_http()
+        .make_span_with(DefaultMakeSpan::default().include_headers(false));
+    Router::new()
+        .nest_service("/assets", ServeDir::new("dist"))
+        .route("/healthz", get(health::healthz))
+        .route("/readyz", get(health::readyz))
+        .route("/metrics", get(metrics::metrics_handler))
+        .route("/api/version", get(health::version))
+        .route("/api/internal_state", delete(delete_internal_state))
+        .route("/api/openapi.json", get(openapi_document))
+        .route("/api/game", post(create_game))
+        .route("/api/game/:game_id", get(game_handler))
+        .route(
+            "/api/game/:game_id/stories",
+            get(api::list_stories).post(api::add_stories),
+        )
+        .route(
+            "/api/game/:game_id/stories/:story_id",
+            patch(api::update_story).delete(api::remove_story),
+        )
+        .route("/api/game/:game_id/actions", post(api::post_action))
+        .route(
+            "/api/game/:game_id/webhooks",
+            get(webhooks::list_webhooks).post(webhooks::add_webhook),
+        )
+        .route(
+            "/api/game/:game_id/webhooks/:webhook_id",
+            delete(webhooks::remove_webhook),
+        )
+        .route(
+            "/api/game/:game_id/webhooks/deliveries",
+            get(webhooks::list_deliveries),
+        )
+        .route("/api/game/:game_id/events", get(game_events))
+        .route("/api/game/:game_id/export.csv", get(export_estimates))
+        .route("/api/game/:game_id/stories/import", post(import_stories))
+        .route("/api/game/:game_id/report.md", get(markdown_report))
+        .route("/api/game/:game_id/report.json", get(json_report))
+        .layer(tracing_layer)
+        .layer(Extension(Arc::new(secret)))
+        .layer(Extension(app_state))
+}
+
 async fn delete_internal_state(
     AuthBearer(token): AuthBearer,
     Extension(state): Extension<Arc<AppState>>,
@@ -88,6 +237,7 @@ async fn delete_internal_state(
                 async { state.games.lock().await.clear() },
                 async { state.channels.write().await.clear() },
             };
+            state.writer.clear();
             (StatusCode::OK, "State cleared successfully")
         }
         Some(_) => (StatusCode::UNAUTHORIZED, "Wrong token"),
@@ -95,111 +245,786 @@ async fn delete_internal_state(
     }
 }
 
+#[allow(clippy::unused_async)]
+async fn openapi_document() -> impl IntoResponse {
+    Json(openapi::document())
+}
+
 async fn create_game(
     Extension(state): Extension<Arc<AppState>>,
-    Json(user): Json<User>,
+    Json(NewGame { user, deck }): Json<NewGame>,
 ) -> impl IntoResponse {
-    let game = Game::new(user);
-    let (tx, _rx) = broadcast::channel(100);
-    let id = game.id;
+    let token = SessionToken::random();
+    let user_id = user.id;
+    let mut record = GameRecord::new(user, deck);
+    record.sessions.insert(user_id, token);
+    let id = record.game.id;
+    state.persist(&record);
 
     tokio::join! {
-        async { state.channels.write().await.insert(id, tx) },
-        async { state.games.lock().await.insert(id, game) },
+        async { state.channels.write().await.insert(id, new_channel()) },
+        async { state.games.lock().await.insert(id, record) },
     };
 
-    (StatusCode::CREATED, Json(id))
+    (StatusCode::CREATED, Json(GameCreated { id, token }))
 }
 
-#[allow(clippy::unused_async)]
-async fn ws_handler(
-    ws: WebSocketUpgrade,
+async fn game_events(
+    Path(game_id): Path<GameId>,
+    Extension(state): Extension<Arc<AppState>>,
+) -> Response {
+    if let Some(record) = state.games.lock().await.get(&game_id) {
+        Json(record.log.clone()).into_response()
+    } else {
+        StatusCode::NOT_FOUND.into_response()
+    }
+}
+
+async fn export_estimates(
+    Path(game_id): Path<GameId>,
+    Extension(state): Extension<Arc<AppState>>,
+) -> Response {
+    let games = state.games.lock().await;
+    let Some(record) = games.get(&game_id) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    match backlog_csv::write_estimates(&record.game.estimated_stories, &record.game.deck) {
+        Ok(data) => (
+            [
+                (header::CONTENT_TYPE, "text/csv; charset=utf-8".to_string()),
+                (
+                    header::CONTENT_DISPOSITION,
+                    format!("attachment; filename=\"estimates-{game_id}.csv\""),
+                ),
+            ],
+            data,
+        )
+            .into_response(),
+        Err(err) => {
+            tracing::error!("error exporting estimates of {:?}: {}", game_id, err);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+async fn markdown_report(
+    Path(game_id): Path<GameId>,
+    Extension(state): Extension<Arc<AppState>>,
+) -> Response {
+    if let Some(record) = state.games.lock().await.get(&game_id) {
+        let report = GameSummary::new(&record.game).to_markdown();
+        (
+            [(header::CONTENT_TYPE, "text/markdown; charset=utf-8")],
+            report,
+        )
+            .into_response()
+    } else {
+        StatusCode::NOT_FOUND.into_response()
+    }
+}
+
+async fn json_report(
+    Path(game_id): Path<GameId>,
+    Extension(state): Extension<Arc<AppState>>,
+) -> Response {
+    if let Some(record) = state.games.lock().await.get(&game_id) {
+        Json(GameSummary::new(&record.game)).into_response()
+    } else {
+        StatusCode::NOT_FOUND.into_response()
+    }
+}
+
+#[derive(Deserialize)]
+struct ImportParams {
+    format: Option<ImportFormat>,
+}
+
+// adds the stories from a saved Jira or GitHub export, allowed only to admins of the game
+async fn import_stories(
+    AuthBearer(token): AuthBearer,
+    Path(game_id): Path<GameId>,
+    Query(params): Query<ImportParams>,
+    Extension(state): Extension<Arc<AppState>>,
+    body: String,
+) -> Response {
+    let Some(admin_id) = admin_by_token(&state, game_id, &token).await else {
+        return StatusCode::UNAUTHORIZED.into_response();
+    };
+    let Some(format) = params.format.or_else(|| ImportFormat::detect(&body)) else {
+        return (StatusCode::BAD_REQUEST, ImportError::UnknownFormat.to_string()).into_response();
+    };
+    let stories = match issue_import::import_stories(format, &body) {
+        Ok(stories) => stories,
+        Err(err) => return (StatusCode::BAD_REQUEST, err.to_string()).into_response(),
+    };
+
+    let Some(tx) = state.channels.read().await.get(&game_id).cloned() else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let action = GameAction::StoriesAdded(stories.clone());
+    if let Err(err) = update_state_on_message(&state, &tx, admin_id, game_id, action).await {
+        return (StatusCode::FORBIDDEN, err.to_string()).into_response();
+    }
+    (StatusCode::CREATED, Json(stories)).into_response()
+}
+
+// player of the game the session token was issued to
+async fn player_by_token(state: &AppState, game_id: GameId, token: &str) -> Option<UserId> {
+    let token: SessionToken = token.parse().ok()?;
+    let games = state.games.lock().await;
+    let record = games.get(&game_id)?;
+    record
+        .sessions
+        .iter()
+        .find(|(_, issued)| **issued == token)
+        .map(|(user_id, _)| *user_id)
+}
+
+// admin of the game the session token was issued to
+async fn admin_by_token(state: &AppState, game_id: GameId, token: &str) -> Option<UserId> {
+    let token: SessionToken = token.parse().ok()?;
+    let games = state.games.lock().await;
+    let record = games.get(&game_id)?;
+    record
+        .sessions
+        .iter()
+        .find(|(user_id, issued)| **issued == token && record.game.is_user_admin(user_id))
+        .map(|(user_id, _)| *user_id)
+}
+
+// the websocket of the game, plain requests get a snapshot of it
+async fn game_handler(
+    ws: Option<WebSocketUpgrade>,
     Path(game_id): Path<GameId>,
     Extension(state): Extension<Arc<AppState>>,
 ) -> Response {
-    ws.on_upgrade(move |socket| handle_socket(socket, state, game_id))
+    match ws {
+        Some(ws) => ws.on_upgrade(move |socket| handle_socket(socket, state, game_id)),
+        None => api::game_snapshot(&state, game_id).await,
+    }
 }
 
 async fn handle_socket(socket: WebSocket, state: Arc<AppState>, game_id: GameId) {
     // By splitting we can send and receive at the same time.
-    let (mut ws_sender, mut ws_receiver) = socket.split();
+    let (mut ws_sender, ws_receiver) = socket.split();
 
-    let api_response = if let Some(game) = state.games.lock().await.get(&game_id) {
-        AppEvent::CurrentState(game.clone())
+    // subscribe before taking the snapshot so no update gets lost in between
+    let tx = state.channels.read().await.get(&game_id).cloned();
+    let rx = tx.as_ref().map(broadcast::Sender::subscribe);
+
+    let api_response = if let Some(record) = state.games.lock().await.get(&game_id) {
+        AppEvent::CurrentState(record.game.clone())
     } else {
         tracing::warn!("game not found: {:?}", game_id);
         AppEvent::GameNotFound(game_id)
     };
 
-    // send the current state (or game not found) to the player who joined
-    let api_response = serde_json::to_string(&api_response).unwrap();
-    if let Err(err) = ws_sender.send(Message::Text(api_response)).await {
-        tracing::error!("error sending game state: {:?}", err);
+    // announce the protocol version and the time, then send the current state (or game not found)
+    let events = [
+        AppEvent::Hello(PROTOCOL_VERSION),
+        AppEvent::ServerTime(now()),
+        api_response,
+    ];
+    for event in events {
+        let event = serde_json::to_string(&event).unwrap();
+        if let Err(err) = ws_sender.send(Message::Text(event)).await {
+            tracing::error!("error sending game state: {:?}", err);
+        }
     }
 
-    let tx = {
-        let channels = state.channels.read().await;
-        if let Some(tx) = channels.get(&game_id) {
-            tx.clone()
-        } else {
-            return;
+    let (Some(tx), Some(rx)) = (tx, rx) else {
+        return;
+    };
+    state.metrics.connected_sockets.inc();
+    // messages addressed only to the player on this connection
+    let (direct_tx, direct_rx) = mpsc::unbounded_channel::<AppEvent>();
+    // the player this connection is bound to, once it joined the game
+    let bound_player = Arc::new(Mutex::new(None));
+    let mut send_task = tokio::spawn(send_events(
+        ws_sender,
+        rx,
+        direct_rx,
+        state.clone(),
+        game_id,
+    ));
+    let mut recv_task = tokio::spawn(receive_events(
+        ws_receiver,
+        state.clone(),
+        tx.clone(),
+        direct_tx,
+        game_id,
+        bound_player.clone(),
+    ));
+
+    // If any one of the tasks exit, abort the other.
+    tokio::select! {
+        _ = (&mut send_task) => recv_task.abort(),
+        _ = (&mut recv_task) => send_task.abort(),
+    };
+    state.metrics.connected_sockets.dec();
+    // the player leaves whichever of the tasks ended first
+    let player_id = *bound_player.lock().await;
+    if let Some(user_id) = player_id {
+        tokio::spawn(leave_game(state, tx, game_id, user_id));
+    }
+}
+
+async fn current_state(state: &AppState, game_id: GameId) -> Option<AppEvent> {
+    let games = state.games.lock().await;
+    let record = games.get(&game_id)?;
+    Some(AppEvent::CurrentState(record.game.clone()))
+}
+
+// check the token of the joining user, issue a new one if the user joins for the first time
+async fn start_session(
+    state: &AppState,
+    game_id: GameId,
+    user_id: UserId,
+    token: Option<SessionToken>,
+) -> Option<SessionToken> {
+    let mut games = state.games.lock().await;
+    let record = games.get_mut(&game_id)?;
+    match (record.sessions.get(&user_id), token) {
+        (Some(issued), Some(token)) if *issued == token => Some(token),
+        (Some(_), _) => None,
+        (None, _) => {
+            let token = SessionToken::random();
+            record.sessions.insert(user_id, token);
+            state.persist(record);
+            Some(token)
+        }
+    }
+}
+
+// a connection plays only as the player it's bound to, and only the actions of clients
+fn check_sender(
+    player_id: Option<UserId>,
+    user_id: UserId,
+    action: &GameAction,
+) -> Result<UserId, (ErrorCode, String)> {
+    match player_id {
+        Some(id) if id == user_id && is_client_action(action) => Ok(id),
+        Some(_) => {
+            let message = format!("action not allowed for {user_id:?}: {action:?}");
+            Err((ErrorCode::InvalidMessage, message))
+        }
+        None => {
+            let message = "the game has to be joined first".to_string();
+            Err((ErrorCode::NotJoined, message))
+        }
+    }
+}
+
+// players can join only through the session handshake, some actions are issued only by the server
+fn is_client_action(action: &GameAction) -> bool {
+    !matches!(
+        action,
+        GameAction::PlayerJoined(_)
+            | GameAction::AdminClaimed
+            | GameAction::TimerExpired(_)
+            | GameAction::PresenceChanged(Presence::Disconnected, _)
+    )
+}
+
+// the server clock is the only one every client agrees on, only the duration of timers is kept
+fn stamp_action(action: GameAction) -> Result<GameAction, GameError> {
+    let action = match action {
+        GameAction::TimerStarted(timer) if timer.duration_ms() > RoundTimer::MAX_DURATION_MS => {
+            return Err(GameError::TimerTooLong);
         }
+        GameAction::TimerStarted(timer) => GameAction::TimerStarted(RoundTimer::new(
+            now(),
+            timer.duration_ms(),
+            timer.auto_reveal,
+        )),
+        GameAction::VotingOpened(story_id, _) => GameAction::VotingOpened(story_id, now()),
+        GameAction::VotingClosed(_) => GameAction::VotingClosed(now()),
+        GameAction::VotesCleared(_) => GameAction::VotesCleared(now()),
+        GameAction::ResultsApproved(estimate, _) => GameAction::ResultsApproved(estimate, now()),
+        GameAction::PresenceChanged(presence, _) => GameAction::PresenceChanged(presence, now()),
+        action => action,
     };
-    let mut rx = tx.subscribe();
-    let mut send_task = tokio::spawn(async move {
-        while let Ok(msg) = rx.recv().await {
-            // break in case of any websocket error
-            if ws_sender.send(Message::Text(msg)).await.is_err() {
-                break;
-            }
+    Ok(action)
+}
+
+// timers of restored games keep running, attributed to the creator of the game
+async fn resume_timers(state: &Arc<AppState>) {
+    let games = state.games.lock().await;
+    let channels = state.channels.read().await;
+    for record in games.values() {
+        let timer = record
+            .game
+            .selected_story
+            .as_ref()
+            .and_then(|story| story.timer);
+        if let (Some(timer), Some(tx)) = (timer, channels.get(&record.game.id)) {
+            let creator_id = record.log.creator.id;
+            tokio::spawn(expire_timer(
+                state.clone(),
+                tx.clone(),
+                record.game.id,
+                creator_id,
+                timer,
+            ));
         }
-    });
-    let mut recv_task = tokio::spawn(async move {
-        // id of the user for PlayerLeft action
-        let mut player_id: Option<UserId> = None;
-
-        // TODO: maybe do some logging here for None, Err, other Message cases
-        while let Some(Ok(Message::Text(data))) = ws_receiver.next().await {
-            if let AppEvent::GameMessage(user_id, action) = serde_json::from_str(&data).unwrap() {
-                if let GameAction::PlayerJoined(user) = &action {
-                    player_id = Some(user.id);
-                    tracing::info!("player_id set to: {:?}", user.id);
+    }
+}
+
+// end the round timer unless it was stopped or restarted in the meantime
+async fn expire_timer(
+    state: Arc<AppState>,
+    tx: broadcast::Sender<String>,
+    game_id: GameId,
+    user_id: UserId,
+    timer: RoundTimer,
+) {
+    tokio::time::sleep(Duration::from_millis(timer.remaining_ms(now()))).await;
+    // applying a stale expiry changes nothing, so it doesn't get broadcast
+    let action = GameAction::TimerExpired(timer.ends_at);
+    update_state_on_message(&state, &tx, user_id, game_id, action)
+        .await
+        .ok();
+}
+
+fn broadcast_message(
+    tx: &broadcast::Sender<String>,
+    seq: u64,
+    user_id: UserId,
+    action: GameAction,
+) {
+    let msg = AppEvent::GameUpdate(seq, user_id, action);
+    let msg = serde_json::to_string(&msg).unwrap();
+    // there are no receivers only if every connection is already gone
+    tx.send(msg).ok();
+}
+
+// forward the updates of the game and the direct messages to the player, pinging it meanwhile
+async fn send_events(
+    mut ws_sender: SplitSink<WebSocket, Message>,
+    mut rx: broadcast::Receiver<String>,
+    mut direct_rx: mpsc::UnboundedReceiver<AppEvent>,
+    state: Arc<AppState>,
+    game_id: GameId,
+) {
+    let mut heartbeat = tokio::time::interval(HEARTBEAT_INTERVAL);
+    loop {
+        let msg = tokio::select! {
+            msg = rx.recv() => match msg {
+                Ok(msg) => Message::Text(msg),
+                // the client missed some updates, send it the whole game instead
+                Err(RecvError::Lagged(skipped)) => {
+                    tracing::warn!("connection lagged behind by {} updates", skipped);
+                    state.metrics.lagged_updates.inc_by(skipped);
+                    let Some(event) = current_state(&state, game_id).await else {
+                        break;
+                    };
+                    Message::Text(serde_json::to_string(&event).unwrap())
                 }
+                Err(RecvError::Closed) => break,
+            },
+            Some(event) = direct_rx.recv() => Message::Text(serde_json::to_string(&event).unwrap()),
+            _ = heartbeat.tick() => Message::Ping(Vec::new()),
+        };
+        // break in case of any websocket error
+        if ws_sender.send(msg).await.is_err() {
+            break;
+        }
+    }
+}
+
+// handle the events sent by the player until the connection closes
+async fn receive_events(
+    mut ws_receiver: SplitStream<WebSocket>,
+    state: Arc<AppState>,
+    tx: broadcast::Sender<String>,
+    direct_tx: mpsc::UnboundedSender<AppEvent>,
+    game_id: GameId,
+    bound_player: Arc<Mutex<Option<UserId>>>,
+) {
+    // id of the user this connection is bound to, shared for the PlayerLeft action
+    let mut player_id: Option<UserId> = None;
+    let mut protocol_version: Option<u32> = None;
+    // number of the message that is processed, errors refer to it
+    let mut request_id = 0;
+    let send_error = |code: ErrorCode, message: String, request_id: u64| {
+        tracing::warn!("rejected message #{}: {}", request_id, message);
+        direct_tx
+            .send(AppEvent::Error {
+                code,
+                message,
+                request_id: Some(request_id),
+            })
+            .ok();
+    };
 
-                update_state_on_message(&state, user_id, game_id, action).await;
-                // send the message to every subscriber
-                tx.send(data).unwrap();
+    // a connection that doesn't even answer the pings is gone, even if it wasn't closed
+    while let Ok(Some(Ok(message))) = timeout(HEARTBEAT_TIMEOUT, ws_receiver.next()).await {
+        let data = match message {
+            Message::Text(data) => data,
+            Message::Close(_) => break,
+            Message::Binary(_) | Message::Ping(_) | Message::Pong(_) => continue,
+        };
+        request_id += 1;
+        let event = match serde_json::from_str(&data) {
+            Ok(event) => event,
+            Err(err) => {
+                send_error(ErrorCode::InvalidMessage, err.to_string(), request_id);
+                continue;
+            }
+        };
+        match (protocol_version, event) {
+            (_, AppEvent::Hello(version)) if version == PROTOCOL_VERSION => {
+                protocol_version = Some(version);
+            }
+            (_, AppEvent::Hello(version)) => {
+                let message = format!(
+                    "protocol version {version} isn't supported, expected {PROTOCOL_VERSION}"
+                );
+                send_error(ErrorCode::UnsupportedProtocol, message, request_id);
+            }
+            (None, _) => {
+                let message = "the protocol version has to be sent first".to_string();
+                send_error(ErrorCode::HandshakeRequired, message, request_id);
+            }
+            (Some(_), AppEvent::ResyncRequested) => {
+                if let Some(event) = current_state(&state, game_id).await {
+                    direct_tx.send(event).ok();
+                }
+            }
+            (Some(_), AppEvent::JoinGame(user, token)) => {
+                if matches!(player_id, Some(id) if id != user.id) {
+                    tracing::warn!("connection already bound to {:?}", player_id);
+                    direct_tx.send(AppEvent::SessionRejected).ok();
+                    continue;
+                }
+                let user_id = user.id;
+                let rejoin = player_id.is_some();
+                if let Some(token) = join_game(&state, &tx, game_id, user, token, rejoin).await {
+                    player_id = Some(user_id);
+                    *bound_player.lock().await = player_id;
+                    tracing::info!("player_id set to: {:?}", user_id);
+                    direct_tx.send(AppEvent::SessionStarted(token)).ok();
+                } else {
+                    tracing::warn!("session token rejected for user: {:?}", user_id);
+                    direct_tx.send(AppEvent::SessionRejected).ok();
+                }
+            }
+            (Some(_), AppEvent::GameMessage(user_id, action)) => {
+                match check_sender(player_id, user_id, &action) {
+                    Ok(id) => {
+                        if let Err(err) = play_action(&state, &tx, game_id, id, action).await {
+                            send_error(ErrorCode::ActionRejected(err), err.to_string(), request_id);
+                        }
+                    }
+                    Err((code, message)) => send_error(code, message, request_id),
+                }
+            }
+            (
+                Some(_),
+                AppEvent::ServerTime(_)
+                | AppEvent::CurrentState(_)
+                | AppEvent::GameNotFound(_)
+                | AppEvent::SessionStarted(_)
+                | AppEvent::SessionRejected
+                | AppEvent::GameUpdate(_, _, _)
+                | AppEvent::Error { .. },
+            ) => {
+                let message = "only the server sends this event".to_string();
+                send_error(ErrorCode::InvalidMessage, message, request_id);
             }
         }
-        //  send a message that the player disconnected to others
-        if let Some(user_id) = player_id {
-            update_state_on_message(&state, user_id, game_id, GameAction::PlayerLeft).await;
-            let msg = AppEvent::GameMessage(user_id, GameAction::PlayerLeft);
-            let msg = serde_json::to_string(&msg).unwrap();
-            tx.send(msg).unwrap();
-        } else {
-            tracing::warn!("PlayerLeft message wasn't set");
-        }
-    });
+    }
+}
 
-    // If any one of the tasks exit, abort the other.
-    tokio::select! {
-        _ = (&mut send_task) => recv_task.abort(),
-        _ = (&mut recv_task) => send_task.abort(),
+// start a session of the user and let others know the player joined,
+// a player resuming within the grace period just keeps playing
+async fn join_game(
+    state: &Arc<AppState>,
+    tx: &broadcast::Sender<String>,
+    game_id: GameId,
+    user: User,
+    token: Option<SessionToken>,
+    rejoin: bool,
+) -> Option<SessionToken> {
+    let token = start_session(state, game_id, user.id, token).await?;
+    let user_id = user.id;
+    let mut games = state.games.lock().await;
+    let record = games.get_mut(&game_id)?;
+    // the connection is counted only once, even if it joins again
+    if !rejoin {
+        record.connect(user_id);
+    }
+    apply_to_record(state, tx, record, user_id, GameAction::PlayerJoined(user)).ok();
+    Some(token)
+}
+
+// let others know the player disconnected, unless the player reconnects in the meantime
+async fn leave_game(
+    state: Arc<AppState>,
+    tx: broadcast::Sender<String>,
+    game_id: GameId,
+    user_id: UserId,
+) {
+    let still_connected = match state.games.lock().await.get_mut(&game_id) {
+        Some(record) => record.disconnect(user_id),
+        None => return,
+    };
+    if still_connected {
+        return;
+    }
+    let action = GameAction::PresenceChanged(Presence::Disconnected, now());
+    update_state_on_message(&state, &tx, user_id, game_id, action)
+        .await
+        .ok();
+    tokio::time::sleep(state.grace_period).await;
+    let mut games = state.games.lock().await;
+    let Some(record) = games.get_mut(&game_id) else {
+        return;
     };
+    if !record.is_connected(&user_id) {
+        apply_to_record(&state, &tx, record, user_id, GameAction::PlayerLeft).ok();
+    }
 }
 
-// update our "global" copy of state
+// apply an action sent by the player and broadcast it when it changed the game
+async fn play_action(
+    state: &Arc<AppState>,
+    tx: &broadcast::Sender<String>,
+    game_id: GameId,
+    user_id: UserId,
+    action: GameAction,
+) -> Result<Vec<GameEvent>, GameError> {
+    let action = stamp_action(action)?;
+    let events = update_state_on_message(state, tx, user_id, game_id, action.clone()).await?;
+    if events.is_empty() {
+        return Ok(events);
+    }
+    if let GameAction::TimerStarted(timer) = action {
+        tokio::spawn(expire_timer(
+            state.clone(),
+            tx.clone(),
+            game_id,
+            user_id,
+            timer,
+        ));
+    }
+    Ok(events)
+}
+
+// update our "global" copy of state and send the change to every subscriber
 async fn update_state_on_message(
     state: &AppState,
+    tx: &broadcast::Sender<String>,
     user_id: UserId,
     game_id: GameId,
     action: GameAction,
-) {
+) -> Result<Vec<GameEvent>, GameError> {
+    let started = Instant::now();
     let mut games = state.games.lock().await;
-    if let Some(game) = games.get_mut(&game_id) {
-        game.update(user_id, action);
-    } else {
+    let Some(record) = games.get_mut(&game_id) else {
         tracing::warn!("trying to update game that doesn't exists");
+        // nobody plays a game that doesn't exist
+        return Err(GameError::NotAPlayer);
+    };
+    let result = apply_to_record(state, tx, record, user_id, action);
+    state
+        .metrics
+        .update_duration
+        .observe(started.elapsed().as_secs_f64());
+    result
+}
+
+// the caller holds the lock of the games, so the updates go out in the order of their numbers
+fn apply_to_record(
+    state: &AppState,
+    tx: &broadcast::Sender<String>,
+    record: &mut GameRecord,
+    user_id: UserId,
+    action: GameAction,
+) -> Result<Vec<GameEvent>, GameError> {
+    let result = record.apply(user_id, action.clone(), now());
+    state.metrics.count_action(&action, result.is_err());
+    let events = result?;
+    if !events.is_empty() {
+        // presence changes all the time, it's written along with the next change of the game
+        if !matches!(action, GameAction::PresenceChanged(_, _)) {
+            state.persist(record);
+        }
+        broadcast_message(tx, record.game.seq, user_id, action);
+        state
+            .webhooks
+            .dispatch(&record.game, &record.webhooks, &events);
+    }
+    Ok(events)
+}
+
+#[allow(clippy::cast_possible_truncation)]
+fn now() -> Timestamp {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since_epoch| since_epoch.as_millis() as Timestamp)
+}
+
+// remove games nobody has been connected to for longer than `ttl`, archiving them if asked to
+async fn reap_games(state: Arc<AppState>, ttl: Duration, archive: bool) {
+    let mut interval = tokio::time::interval(Duration::from_secs(60));
+    loop {
+        interval.tick().await;
+        let mut games = state.games.lock().await;
+        let mut channels = state.channels.write().await;
+        let expired = games
+            .values()
+            .filter(|record| record.is_expired(ttl))
+            .map(|record| record.game.id)
+            .collect::<Vec<_>>();
+        for game_id in &expired {
+            let Some(record) = games.remove(game_id) else {
+                continue;
+            };
+            channels.remove(game_id);
+            state.webhooks.forget(game_id).await;
+            if archive {
+                state.writer.archive(record);
+            } else {
+                state.writer.remove(*game_id);
+            }
+        }
+        if !expired.is_empty() {
+            let count = expired.len() as u64;
+            state.metrics.reaped_games.inc_by(count);
+            let total = state.metrics.reaped_games.get();
+            tracing::info!("reaped {} abandoned games, {} in total", count, total);
+        }
+    }
+}
+
+// promote the longest connected player in games whose admins are gone for too long
+async fn hand_off_admin(state: Arc<AppState>, timeout: Duration) {
+    let mut interval = tokio::time::interval(Duration::from_secs(30));
+    loop {
+        interval.tick().await;
+        let mut games = state.games.lock().await;
+        let channels = state.channels.read().await;
+        for record in games.values_mut() {
+            let (Some(user_id), Some(tx)) = (
+                record.admin_candidate(timeout),
+                channels.get(&record.game.id),
+            ) else {
+                continue;
+            };
+            if apply_to_record(&state, tx, record, user_id, GameAction::AdminClaimed).is_ok() {
+                tracing::info!("admin handed off to {:?} in {:?}", user_id, record.game.id);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::{Deck, Vote};
+
+    #[test]
+    fn timers_get_the_server_time() {
+        let timer = RoundTimer::new(0, 60_000, true);
+        let Ok(GameAction::TimerStarted(stamped)) = stamp_action(GameAction::TimerStarted(timer))
+        else {
+            panic!("the timer was rejected");
+        };
+        assert!(stamped.started_at > 0);
+        assert_eq!(stamped.duration_ms(), 60_000);
+    }
+
+    #[test]
+    fn timers_longer_than_an_hour_are_rejected() {
+        let timer = RoundTimer {
+            started_at: 0,
+            ends_at: u64::MAX,
+            auto_reveal: false,
+        };
+        let result = stamp_action(GameAction::TimerStarted(timer));
+        assert!(matches!(result, Err(GameError::TimerTooLong)));
+        assert_eq!(RoundTimer::new(u64::MAX - 1, 10, false).ends_at, u64::MAX);
+    }
+
+    #[test]
+    fn only_clients_actions_are_accepted_from_clients() {
+        for action in [
+            GameAction::PlayerJoined(User::new("Alice".to_string())),
+            GameAction::AdminClaimed,
+            GameAction::TimerExpired(0),
+            GameAction::PresenceChanged(Presence::Disconnected, 0),
+        ] {
+            assert!(!is_client_action(&action), "{action:?}");
+        }
+        for action in [
+            GameAction::VoteCasted(Vote::Points(3)),
+            GameAction::PresenceChanged(Presence::Away, 0),
+        ] {
+            assert!(is_client_action(&action), "{action:?}");
+        }
+    }
+
+    #[test]
+    fn connections_play_only_as_their_player() {
+        let (alice, bob) = (
+            User::new("Alice".to_string()).id,
+            User::new("Bob".to_string()).id,
+        );
+        let vote = GameAction::VoteCasted(Vote::Points(3));
+        assert_eq!(check_sender(Some(alice), alice, &vote), Ok(alice));
+        let rejected = check_sender(Some(alice), bob, &vote).unwrap_err();
+        assert_eq!(rejected.0, ErrorCode::InvalidMessage);
+        let rejected = check_sender(Some(alice), alice, &GameAction::AdminClaimed).unwrap_err();
+        assert_eq!(rejected.0, ErrorCode::InvalidMessage);
+        let rejected = check_sender(None, alice, &vote).unwrap_err();
+        assert_eq!(rejected.0, ErrorCode::NotJoined);
+    }
+
+    #[tokio::test]
+    async fn sessions_are_resumed_only_with_their_token() {
+        let state = testing::test_state(Arc::new(MemoryStore));
+        let record = GameRecord::new(User::new("Alice".to_string()), Deck::default());
+        let game_id = record.game.id;
+        state.games.lock().await.insert(game_id, record);
+        let bob = User::new("Bob".to_string()).id;
+
+        let token = start_session(&state, game_id, bob, None).await.unwrap();
+        assert_eq!(start_session(&state, game_id, bob, None).await, None);
+        let wrong_token = SessionToken::random();
+        assert_eq!(
+            start_session(&state, game_id, bob, Some(wrong_token)).await,
+            None
+        );
+        assert_eq!(
+            start_session(&state, game_id, bob, Some(token)).await,
+            Some(token)
+        );
+        let unknown_game = GameId::new(uuid::Uuid::new_v4());
+        assert_eq!(start_session(&state, unknown_game, bob, None).await, None);
+    }
+
+    #[tokio::test]
+    async fn presence_changes_arent_written_on_their_own() {
+        let dir = std::env::temp_dir().join(format!("presence-{}", uuid::Uuid::new_v4()));
+        let store = Arc::new(FileStore::new(&dir).unwrap());
+        let state = testing::test_state(store.clone());
+        let tx = new_channel();
+        let mut record = GameRecord::new(User::new("Alice".to_string()), Deck::default());
+        let admin_id = record.log.creator.id;
+
+        let action = GameAction::AutoRevealChanged(true);
+        apply_to_record(&state, &tx, &mut record, admin_id, action).unwrap();
+        let action = GameAction::PresenceChanged(Presence::Away, 1);
+        apply_to_record(&state, &tx, &mut record, admin_id, action).unwrap();
+        state.writer.flush().await;
+        assert_eq!(store.load().unwrap()[0].game.seq, 1);
+
+        let action = GameAction::AutoRevealChanged(false);
+        apply_to_record(&state, &tx, &mut record, admin_id, action).unwrap();
+        state.writer.flush().await;
+        let stored = store.load().unwrap().remove(0);
+        assert_eq!(stored.game, record.game);
+        std::fs::remove_dir_all(dir).unwrap();
     }
 }
@@ -0,0 +1,214 @@
+//! Prometheus metrics of the backend, served at `/metrics` to the holders of the API secret.
+
+use axum::{
+    extract::Extension,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+};
+use axum_auth::AuthBearer;
+use common::GameAction;
+use prometheus::{
+    core::Collector, histogram_opts, opts, Encoder, Histogram, IntCounter, IntCounterVec, IntGauge,
+    Registry, TextEncoder,
+};
+use std::sync::Arc;
+
+use crate::{AppSecret, AppState};
+
+pub(crate) struct Metrics {
+    registry: Registry,
+    games: IntGauge,
+    /// Games somebody is connected to.
+    active_games: IntGauge,
+    /// Active players of all the games.
+    players: IntGauge,
+    pub(crate) connected_sockets: IntGauge,
+    /// Updates the connections skipped because they fell behind the broadcast channel.
+    pub(crate) lagged_updates: IntCounter,
+    pub(crate) reaped_games: IntCounter,
+    actions: IntCounterVec,
+    pub(crate) update_duration: Histogram,
+}
+
+impl Metrics {
+    pub(crate) fn new() -> Self {
+        let registry = Registry::new_custom(Some("poker".to_string()), None).unwrap();
+        let games = IntGauge::with_opts(opts!("games", "Games kept in memory.")).unwrap();
+        let active_games = IntGauge::with_opts(opts!(
+            "active_games",
+            "Games at least one player is connected to."
+        ))
+        .unwrap();
+        let players =
+            IntGauge::with_opts(opts!("players", "Active players of all the games.")).unwrap();
+        let connected_sockets =
+            IntGauge::with_opts(opts!("connected_sockets", "Open websocket connections.")).unwrap();
+        let lagged_updates = IntCounter::with_opts(opts!(
+            "broadcast_lagged_updates_total",
+            "Game updates skipped by connections lagging behind, resynced with the whole game."
+        ))
+        .unwrap();
+        let reaped_games = IntCounter::with_opts(opts!(
+            "reaped_games_total",
+            "Abandoned games removed since the start."
+        ))
+        .unwrap();
+        let actions = IntCounterVec::new(
+            opts!("game_actions_total", "Actions applied to the games."),
+            &["action", "outcome"],
+        )
+        .unwrap();
+        let update_duration = Histogram::with_opts(histogram_opts!(
+            "game_update_duration_seconds",
+            "Time to apply an action, waiting for the lock of the games included.",
+            vec![0.0001, 0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5]
+        ))
+        .unwrap();
+
+        registry.register(Box::new(games.clone())).unwrap();
+        registry.register(Box::new(active_games.clone())).unwrap();
+        registry.register(Box::new(players.clone())).unwrap();
+        registry
+            .register(Box::new(connected_sockets.clone()))
+            .unwrap();
+        registry.register(Box::new(lagged_updates.clone())).unwrap();
+        registry.register(Box::new(reaped_games.clone())).unwrap();
+        registry.register(Box::new(actions.clone())).unwrap();
+        registry
+            .register(Box::new(update_duration.clone()))
+            .unwrap();
+        Metrics {
+            registry,
+            games,
+            active_games,
+            players,
+            connected_sockets,
+            lagged_updates,
+            reaped_games,
+            actions,
+            update_duration,
+        }
+    }
+
+    /// Counts the action as processed, even if it didn't change anything, or as rejected.
+    pub(crate) fn count_action(&self, action: &GameAction, rejected: bool) {
+        let outcome = if rejected { "rejected" } else { "processed" };
+        self.actions
+            .with_label_values(&[action_name(action), outcome])
+            .inc();
+    }
+}
+
+fn action_name(action: &GameAction) -> &'static str {
+    match action {
+        GameAction::PlayerJoined(_) => "PlayerJoined",
+        GameAction::PlayerLeft => "PlayerLeft",
+        GameAction::StoriesAdded(_) => "StoriesAdded",
+        GameAction::StoryUpdated(_, _) => "StoryUpdated",
+        GameAction::StoryPositionChanged(_, _) => "StoryPositionChanged",
+        GameAction::StoryRemoved(_) => "StoryRemoved",
+        GameAction::VotingOpened(_, _) => "VotingOpened",
+        GameAction::VotingClosed(_) => "VotingClosed",
+        GameAction::VoteCasted(_) => "VoteCasted",
+        GameAction::VotesRevealed => "VotesRevealed",
+        GameAction::VotesCleared(_) => "VotesCleared",
+        GameAction::ResultsApproved(_, _) => "ResultsApproved",
+        GameAction::PlayerRoleChanged(_, _) => "PlayerRoleChanged",
+        GameAction::AdminTransferred(_) => "AdminTransferred",
+        GameAction::AdminClaimed => "AdminClaimed",
+        GameAction::AutoRevealChanged(_) => "AutoRevealChanged",
+        GameAction::TimerStarted(_) => "TimerStarted",
+        GameAction::TimerStopped => "TimerStopped",
+        GameAction::TimerExpired(_) => "TimerExpired",
+        GameAction::PresenceChanged(_, _) => "PresenceChanged",
+    }
+}
+
+// the gauges of the games are taken when scraped, the ids of the games are never exposed,
+// the distribution of the players is built anew for every scrape so it shows the current games
+pub(crate) async fn metrics_handler(
+    AuthBearer(token): AuthBearer,
+    Extension(state): Extension<Arc<AppState>>,
+    Extension(secret): Extension<Arc<AppSecret>>,
+) -> Response {
+    match &secret.0 {
+        Some(secret) if *secret == token => {}
+        Some(_) => return (StatusCode::UNAUTHORIZED, "Wrong token").into_response(),
+        None => return (StatusCode::UNAUTHORIZED, "Secret not set").into_response(),
+    }
+    let metrics = &state.metrics;
+    let players_per_game = Histogram::with_opts(
+        histogram_opts!(
+            "players_per_game",
+            "Active players of every game kept in memory.",
+            vec![1.0, 2.0, 4.0, 8.0, 16.0, 32.0, 64.0]
+        )
+        .namespace("poker"),
+    )
+    .unwrap();
+    {
+        let games = state.games.lock().await;
+        metrics.games.set(games.len() as i64);
+        let active_games = games
+            .values()
+            .filter(|record| !record.connections.is_empty())
+            .count();
+        metrics.active_games.set(active_games as i64);
+        let mut players = 0;
+        for record in games.values() {
+            let count = record.game.to_active_players().len();
+            players += count;
+            #[allow(clippy::cast_precision_loss)]
+            players_per_game.observe(count as f64);
+        }
+        metrics.players.set(players as i64);
+    }
+    let mut families = metrics.registry.gather();
+    families.extend(players_per_game.collect());
+
+    let encoder = TextEncoder::new();
+    let mut buffer = Vec::new();
+    if let Err(err) = encoder.encode(&families, &mut buffer) {
+        tracing::error!("error encoding metrics: {:?}", err);
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+    (
+        [(header::CONTENT_TYPE, encoder.format_type().to_string())],
+        buffer,
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        store::MemoryStore,
+        testing::{call, create_game, get, test_app, SECRET},
+    };
+    use axum::http::{Method, StatusCode};
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn metrics_need_the_secret() {
+        let app = test_app(Arc::new(MemoryStore));
+        let (status, _) = get(app.clone(), "/metrics").await;
+        assert!(status.is_client_error());
+        let (status, _) = call(app.clone(), Method::GET, "/metrics", Some("wrong"), None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn metrics_dont_expose_games() {
+        let app = test_app(Arc::new(MemoryStore));
+        let (created, _) = create_game(app.clone()).await;
+
+        let (status, body) = call(app.clone(), Method::GET, "/metrics", Some(SECRET), None).await;
+        assert_eq!(status, StatusCode::OK);
+        let metrics = String::from_utf8(body).unwrap();
+        assert!(metrics.contains("poker_games 1"));
+        assert!(metrics.contains("poker_players 1"));
+        assert!(metrics.contains("poker_players_per_game_bucket{le=\"1\"} 1"));
+        assert!(metrics.contains("poker_players_per_game_count 1"));
+        assert!(!metrics.contains(&created.id.to_string()));
+    }
+}
@@ -0,0 +1,367 @@
+//! `OpenAPI` document of the HTTP routes, the schemas come from the `common` types.
+
+use serde_json::{json, Value};
+
+pub(crate) fn document() -> Value {
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "Planning poker",
+            "version": env!("CARGO_PKG_VERSION"),
+            "description": "Players connect to `GET /api/game/{game_id}` with a websocket and \
+                exchange `AppEvent` messages, the other routes serve integrations.",
+        },
+        "components": {
+            "schemas": common::schema::openapi_schemas(),
+            "securitySchemes": {
+                "session": {
+                    "type": "http",
+                    "scheme": "bearer",
+                    "description": "Session token the server issued to a player of the game.",
+                },
+            },
+        },
+        "paths": paths(),
+    })
+}
+
+fn paths() -> Value {
+    let game_id = path_param("game_id", "Id of the game.");
+    let story_id = path_param("story_id", "Id of the story.");
+    let mut paths = game_paths(&game_id);
+    let webhook_id = path_param("webhook_id", "Id of the webhook.");
+    let more_paths = [
+        story_paths(&game_id, &story_id),
+        webhook_paths(&game_id, &webhook_id),
+        export_paths(&game_id),
+        service_paths(),
+    ];
+    for more in more_paths {
+        if let (Value::Object(paths), Value::Object(more)) = (&mut paths, more) {
+            paths.extend(more);
+        }
+    }
+    paths
+}
+
+// creating, joining and playing games
+fn game_paths(game_id: &Value) -> Value {
+    json!({
+        "/api/game": {
+            "post": {
+                "summary": "Create a game",
+                "requestBody": json_body("NewGame"),
+                "responses": {
+                    "201": json_response("The game and the session token of its creator.", "GameCreated"),
+                },
+            },
+        },
+        "/api/game/{game_id}": {
+            "parameters": [game_id],
+            "get": {
+                "summary": "Snapshot of the game, or its websocket when asked to upgrade",
+                "responses": {
+                    "101": { "description": "The websocket, exchanging `AppEvent` messages." },
+                    "200": json_response("Current state of the game.", "Game"),
+                    "404": not_found(),
+                },
+            },
+        },
+        "/api/game/{game_id}/actions": {
+            "parameters": [game_id],
+            "post": {
+                "summary": "Play an action as the player of the session",
+                "security": [{ "session": [] }],
+                "requestBody": json_body("GameAction"),
+                "responses": {
+                    "200": {
+                        "description": "What the action changed, nothing if it was a no-op.",
+                        "content": { "application/json": { "schema": array_of("GameEvent") } },
+                    },
+                    "401": unauthorized(),
+                    "403": rejected(),
+                    "404": not_found(),
+                    "409": { "description": "The action doesn't fit the state of the game." },
+                },
+            },
+        },
+    })
+}
+
+// managing the stories
+fn story_paths(game_id: &Value, story_id: &Value) -> Value {
+    json!({
+        "/api/game/{game_id}/stories": {
+            "parameters": [game_id],
+            "get": {
+                "summary": "Stories of the game",
+                "responses": {
+                    "200": {
+                        "description": "Backlog, the story open for voting and the estimated ones.",
+                        "content": { "application/json": { "schema": {
+                            "type": "object",
+                            "required": ["backlog", "estimated"],
+                            "properties": {
+                                "backlog": array_of("BacklogStory"),
+                                "selected": schema_ref("SelectedStory"),
+                                "estimated": array_of("EstimatedStory"),
+                            },
+                        } } },
+                    },
+                    "404": not_found(),
+                },
+            },
+            "post": {
+                "summary": "Add stories to the backlog",
+                "security": [{ "session": [] }],
+                "requestBody": {
+                    "required": true,
+                    "content": { "application/json": { "schema": array_of("StoryInfo") } },
+                },
+                "responses": {
+                    "201": {
+                        "description": "The added stories.",
+                        "content": { "application/json": { "schema": array_of("BacklogStory") } },
+                    },
+                    "401": unauthorized(),
+                    "403": rejected(),
+                    "404": not_found(),
+                },
+            },
+        },
+        "/api/game/{game_id}/stories/{story_id}": {
+            "parameters": [game_id, story_id],
+            "patch": {
+                "summary": "Update a backlog story, fields missing in the body keep their values",
+                "security": [{ "session": [] }],
+                "requestBody": json_body("StoryInfo"),
+                "responses": {
+                    "200": json_response("The updated story.", "BacklogStory"),
+                    "400": { "description": "The fields aren't valid." },
+                    "401": unauthorized(),
+                    "403": rejected(),
+                    "404": not_found(),
+                },
+            },
+            "delete": {
+                "summary": "Remove a backlog story",
+                "security": [{ "session": [] }],
+                "responses": {
+                    "204": { "description": "The story was removed." },
+                    "401": unauthorized(),
+                    "403": rejected(),
+                    "404": not_found(),
+                },
+            },
+        },
+        "/api/game/{game_id}/stories/import": {
+            "parameters": [game_id],
+            "post": {
+                "summary": "Import stories from a Jira or GitHub export",
+                "security": [{ "session": [] }],
+                "parameters": [{
+                    "name": "format",
+                    "in": "query",
+                    "description": "Format of the export, detected when missing.",
+                    "schema": schema_ref("ImportFormat"),
+                }],
+                "requestBody": {
+                    "required": true,
+                    "content": { "text/plain": { "schema": { "type": "string" } } },
+                },
+                "responses": {
+                    "201": {
+                        "description": "The imported stories.",
+                        "content": { "application/json": { "schema": array_of("BacklogStory") } },
+                    },
+                    "400": { "description": "The export couldn't be read." },
+                    "401": unauthorized(),
+                },
+            },
+        },
+    })
+}
+
+// notifications posted to other services, managed by the admins
+fn webhook_paths(game_id: &Value, webhook_id: &Value) -> Value {
+    json!({
+        "/api/game/{game_id}/webhooks": {
+            "parameters": [game_id],
+            "get": {
+                "summary": "Webhooks of the game",
+                "security": [{ "session": [] }],
+                "responses": {
+                    "200": {
+                        "description": "The webhooks, without their secrets.",
+                        "content": { "application/json": { "schema": array_of("Webhook") } },
+                    },
+                    "401": unauthorized(),
+                    "404": not_found(),
+                },
+            },
+            "post": {
+                "summary": "Register a webhook, the events it subscribed to get posted as `WebhookPayload`",
+                "security": [{ "session": [] }],
+                "requestBody": json_body("NewWebhook"),
+                "responses": {
+                    "201": json_response("The registered webhook.", "Webhook"),
+                    "400": { "description": "The URL or the secret isn't valid." },
+                    "401": unauthorized(),
+                    "404": not_found(),
+                },
+            },
+        },
+        "/api/game/{game_id}/webhooks/{webhook_id}": {
+            "parameters": [game_id, webhook_id],
+            "delete": {
+                "summary": "Remove a webhook",
+                "security": [{ "session": [] }],
+                "responses": {
+                    "204": { "description": "The webhook was removed." },
+                    "401": unauthorized(),
+                    "404": { "description": "The game or the webhook doesn't exist." },
+                },
+            },
+        },
+        "/api/game/{game_id}/webhooks/deliveries": {
+            "parameters": [game_id],
+            "get": {
+                "summary": "Latest deliveries to the webhooks of the game",
+                "security": [{ "session": [] }],
+                "responses": {
+                    "200": {
+                        "description": "The deliveries, oldest first.",
+                        "content": { "application/json": { "schema": array_of("WebhookDelivery") } },
+                    },
+                    "401": unauthorized(),
+                },
+            },
+        },
+    })
+}
+
+// logs, exports and reports
+fn export_paths(game_id: &Value) -> Value {
+    json!({
+        "/api/game/{game_id}/events": {
+            "parameters": [game_id],
+            "get": {
+                "summary": "Log of the actions played in the game",
+                "responses": {
+                    "200": json_response("The log.", "GameLog"),
+                    "404": not_found(),
+                },
+            },
+        },
+        "/api/game/{game_id}/export.csv": {
+            "parameters": [game_id],
+            "get": {
+                "summary": "Estimated stories as CSV",
+                "responses": {
+                    "200": {
+                        "description": "The estimates.",
+                        "content": { "text/csv": { "schema": { "type": "string" } } },
+                    },
+                    "404": not_found(),
+                },
+            },
+        },
+        "/api/game/{game_id}/report.md": {
+            "parameters": [game_id],
+            "get": {
+                "summary": "Summary of the session as Markdown",
+                "responses": {
+                    "200": {
+                        "description": "The summary.",
+                        "content": { "text/markdown": { "schema": { "type": "string" } } },
+                    },
+                    "404": not_found(),
+                },
+            },
+        },
+        "/api/game/{game_id}/report.json": {
+            "parameters": [game_id],
+            "get": {
+                "summary": "Summary of the session",
+                "responses": {
+                    "200": json_response("The summary.", "GameSummary"),
+                    "404": not_found(),
+                },
+            },
+        },
+    })
+}
+
+// probes and descriptions of the server itself
+fn service_paths() -> Value {
+    json!({
+        "/healthz": {
+            "get": {
+                "summary": "Whether the server is up",
+                "responses": { "200": { "description": "The server serves requests." } },
+            },
+        },
+        "/readyz": {
+            "get": {
+                "summary": "Whether the server can take games",
+                "responses": {
+                    "200": { "description": "The store of the games is available." },
+                    "503": { "description": "The store of the games isn't available." },
+                },
+            },
+        },
+        "/api/version": {
+            "get": {
+                "summary": "Build of the server",
+                "responses": { "200": json_response("Versions of the server.", "VersionInfo") },
+            },
+        },
+        "/api/openapi.json": {
+            "get": {
+                "summary": "This document",
+                "responses": { "200": { "description": "The OpenAPI document." } },
+            },
+        },
+    })
+}
+
+fn schema_ref(name: &str) -> Value {
+    json!({ "$ref": format!("#/components/schemas/{name}") })
+}
+
+fn array_of(name: &str) -> Value {
+    json!({ "type": "array", "items": schema_ref(name) })
+}
+
+fn json_body(name: &str) -> Value {
+    json!({ "required": true, "content": { "application/json": { "schema": schema_ref(name) } } })
+}
+
+fn json_response(description: &str, name: &str) -> Value {
+    json!({
+        "description": description,
+        "content": { "application/json": { "schema": schema_ref(name) } },
+    })
+}
+
+fn path_param(name: &str, description: &str) -> Value {
+    json!({
+        "name": name,
+        "in": "path",
+        "required": true,
+        "description": description,
+        "schema": { "type": "string", "format": "uuid" },
+    })
+}
+
+fn not_found() -> Value {
+    json!({ "description": "The game or the story doesn't exist." })
+}
+
+fn unauthorized() -> Value {
+    json!({ "description": "The session token doesn't belong to a player of the game." })
+}
+
+fn rejected() -> Value {
+    json!({ "description": "The game rejected the action of the player." })
+}
@@ -0,0 +1,469 @@
+use common::{
+    Deck, Game, GameAction, GameError, GameEvent, GameId, GameLog, SessionToken, Timestamp, User,
+    UserId,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::sync::mpsc;
+
+use crate::webhooks::WebhookRegistration;
+
+/// Everything the backend keeps about a single game.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub(crate) struct GameRecord {
+    pub(crate) game: Game,
+    pub(crate) sessions: HashMap<UserId, SessionToken>,
+    pub(crate) log: GameLog,
+    #[serde(default)]
+    pub(crate) webhooks: Vec<WebhookRegistration>,
+    /// When the active players connected, not kept across restarts.
+    #[serde(skip)]
+    pub(crate) connected_since: HashMap<UserId, Instant>,
+    /// Since when none of the admins is active, not kept across restarts.
+    #[serde(skip)]
+    pub(crate) admins_absent_since: Option<Instant>,
+    /// Open connections of every player, not kept across restarts.
+    #[serde(skip)]
+    pub(crate) connections: HashMap<UserId, usize>,
+    /// Since when nobody is connected to the game, restored games start without anybody.
+    #[serde(skip, default = "abandoned_now")]
+    pub(crate) abandoned_since: Option<Instant>,
+}
+
+#[allow(clippy::unnecessary_wraps)]
+fn abandoned_now() -> Option<Instant> {
+    Some(Instant::now())
+}
+
+impl GameRecord {
+    pub(crate) fn new(creator: User, deck: Deck) -> Self {
+        let game = Game::new(creator.clone(), deck);
+        let log = GameLog::new(game.id, creator, deck);
+        GameRecord {
+            game,
+            sessions: HashMap::new(),
+            log,
+            webhooks: Vec::new(),
+            connected_since: HashMap::new(),
+            admins_absent_since: None,
+            connections: HashMap::new(),
+            abandoned_since: abandoned_now(),
+        }
+    }
+
+    pub(crate) fn connect(&mut self, user_id: UserId) {
+        *self.connections.entry(user_id).or_default() += 1;
+        self.abandoned_since = None;
+        self.connected_since
+            .entry(user_id)
+            .or_insert_with(Instant::now);
+    }
+
+    /// Returns whether the player still has another connection open.
+    pub(crate) fn disconnect(&mut self, user_id: UserId) -> bool {
+        let Some(count) = self.connections.get_mut(&user_id) else {
+            return false;
+        };
+        *count -= 1;
+        if *count == 0 {
+            self.connections.remove(&user_id);
+            if self.connections.is_empty() {
+                self.abandoned_since = abandoned_now();
+            }
+            return false;
+        }
+        true
+    }
+
+    /// Whether nobody has been connected to the game for at least `ttl`.
+    pub(crate) fn is_expired(&self, ttl: Duration) -> bool {
+        matches!(self.abandoned_since, Some(since) if since.elapsed() >= ttl)
+    }
+
+    pub(crate) fn is_connected(&self, user_id: &UserId) -> bool {
+        self.connections.contains_key(user_id)
+    }
+
+    /// Applies the action to the game and logs it, rejected actions leave the record untouched.
+    pub(crate) fn apply(
+        &mut self,
+        user_id: UserId,
+        action: GameAction,
+        timestamp: Timestamp,
+    ) -> Result<Vec<GameEvent>, GameError> {
+        let events = self.game.update(user_id, action.clone())?;
+        // only actions that changed something are kept
+        if events.is_empty() {
+            return Ok(events);
+        }
+        if matches!(action, GameAction::PlayerLeft) {
+            self.connected_since.remove(&user_id);
+        }
+        self.log.push(user_id, action, timestamp);
+
+        if self.game.has_active_admin() {
+            self.admins_absent_since = None;
+        } else {
+            self.admins_absent_since.get_or_insert_with(Instant::now);
+        }
+        Ok(events)
+    }
+
+    /// Longest connected active player, once admins have been away for longer than `timeout`.
+    pub(crate) fn admin_candidate(&self, timeout: Duration) -> Option<UserId> {
+        if self.admins_absent_since?.elapsed() < timeout {
+            return None;
+        }
+        self.connected_since
+            .iter()
+            .filter(|(user_id, _)| matches!(self.game.players.get(*user_id), Some(player) if player.active))
+            .min_by_key(|(_, since)| **since)
+            .map(|(user_id, _)| *user_id)
+    }
+}
+
+/// Storage that keeps games across backend restarts.
+pub(crate) trait GameStore: Send + Sync {
+    fn load(&self) -> io::Result<Vec<GameRecord>>;
+    fn save(&self, record: &GameRecord) -> io::Result<()>;
+    fn remove(&self, game_id: &GameId) -> io::Result<()>;
+    /// Keeps the final state of a game that is removed from the live ones.
+    fn archive(&self, record: &GameRecord) -> io::Result<()>;
+    fn clear(&self) -> io::Result<()>;
+    /// Whether the games can be kept right now.
+    fn check(&self) -> io::Result<()>;
+}
+
+enum StoreCommand {
+    Save(Box<GameRecord>),
+    Remove(GameId),
+    Archive(Box<GameRecord>),
+    Clear,
+    #[cfg(test)]
+    Flush(tokio::sync::oneshot::Sender<()>),
+}
+
+/// Hands the changes to a background task, so nobody waits for the disk while holding the games.
+#[derive(Clone)]
+pub(crate) struct StoreWriter(mpsc::UnboundedSender<StoreCommand>);
+
+impl StoreWriter {
+    pub(crate) fn spawn(store: Arc<dyn GameStore>) -> Self {
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::spawn(write_changes(store, rx));
+        StoreWriter(tx)
+    }
+
+    pub(crate) fn save(&self, record: GameRecord) {
+        self.send(StoreCommand::Save(Box::new(record)));
+    }
+
+    pub(crate) fn remove(&self, game_id: GameId) {
+        self.send(StoreCommand::Remove(game_id));
+    }
+
+    pub(crate) fn archive(&self, record: GameRecord) {
+        self.send(StoreCommand::Archive(Box::new(record)));
+    }
+
+    pub(crate) fn clear(&self) {
+        self.send(StoreCommand::Clear);
+    }
+
+    /// Waits until the changes sent so far are written.
+    #[cfg(test)]
+    pub(crate) async fn flush(&self) {
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        self.send(StoreCommand::Flush(tx));
+        rx.await.ok();
+    }
+
+    fn send(&self, command: StoreCommand) {
+        if self.0.send(command).is_err() {
+            tracing::error!("the store writer is gone, changes aren't kept");
+        }
+    }
+}
+
+// writes the changes in batches of whatever piled up meanwhile, in the order they were made
+async fn write_changes(store: Arc<dyn GameStore>, mut rx: mpsc::UnboundedReceiver<StoreCommand>) {
+    while let Some(command) = rx.recv().await {
+        let mut batch = vec![command];
+        while let Ok(command) = rx.try_recv() {
+            batch.push(command);
+        }
+        let store = store.clone();
+        if let Err(err) = tokio::task::spawn_blocking(move || write_batch(&*store, batch)).await {
+            tracing::error!("error writing to the store: {:?}", err);
+        }
+    }
+}
+
+fn write_batch(store: &dyn GameStore, batch: Vec<StoreCommand>) {
+    // only the latest state of a game is worth writing
+    let superseded = (0..batch.len())
+        .map(|index| match &batch[index] {
+            StoreCommand::Save(record) => batch[index + 1..]
+                .iter()
+                .any(|later| later.replaces(record.game.id)),
+            _ => false,
+        })
+        .collect::<Vec<_>>();
+    for (command, is_superseded) in batch.into_iter().zip(superseded) {
+        let result = match command {
+            _ if is_superseded => Ok(()),
+            StoreCommand::Save(record) => store
+                .save(&record)
+                .map_err(|err| (Some(record.game.id), err)),
+            StoreCommand::Remove(game_id) => {
+                store.remove(&game_id).map_err(|err| (Some(game_id), err))
+            }
+            StoreCommand::Archive(record) => store
+                .archive(&record)
+                .map_err(|err| (Some(record.game.id), err)),
+            StoreCommand::Clear => store.clear().map_err(|err| (None, err)),
+            #[cfg(test)]
+            StoreCommand::Flush(tx) => {
+                tx.send(()).ok();
+                Ok(())
+            }
+        };
+        if let Err((game_id, err)) = result {
+            tracing::error!("error persisting game {:?}: {:?}", game_id, err);
+        }
+    }
+}
+
+impl StoreCommand {
+    /// Whether the command leaves nothing of what was written for the game before.
+    fn replaces(&self, game_id: GameId) -> bool {
+        match self {
+            StoreCommand::Save(record) | StoreCommand::Archive(record) => record.game.id == game_id,
+            StoreCommand::Remove(removed_id) => *removed_id == game_id,
+            StoreCommand::Clear => true,
+            #[cfg(test)]
+            StoreCommand::Flush(_) => false,
+        }
+    }
+}
+
+/// Doesn't persist anything, games live only as long as the process.
+pub(crate) struct MemoryStore;
+
+impl GameStore for MemoryStore {
+    fn load(&self) -> io::Result<Vec<GameRecord>> {
+        Ok(Vec::new())
+    }
+
+    fn save(&self, _record: &GameRecord) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn remove(&self, _game_id: &GameId) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn archive(&self, _record: &GameRecord) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn clear(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn check(&self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Keeps every game as a separate json file in the given directory,
+/// archived games go to its `archive` subdirectory.
+pub(crate) struct FileStore {
+    dir: PathBuf,
+}
+
+impl FileStore {
+    pub(crate) fn new(dir: impl Into<PathBuf>) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(FileStore { dir })
+    }
+
+    fn game_path(&self, game_id: &GameId) -> PathBuf {
+        self.dir.join(format!("{game_id}.json"))
+    }
+
+    fn archive_dir(&self) -> PathBuf {
+        self.dir.join("archive")
+    }
+
+    fn game_files(&self) -> io::Result<impl Iterator<Item = PathBuf>> {
+        Ok(fs::read_dir(&self.dir)?
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().map_or(false, |ext| ext == "json")))
+    }
+}
+
+impl GameStore for FileStore {
+    fn load(&self) -> io::Result<Vec<GameRecord>> {
+        let mut records = Vec::new();
+        for path in self.game_files()? {
+            match read_record(&path) {
+                Ok(record) => records.push(record),
+                Err(err) => tracing::error!("skipping unreadable game {:?}: {:?}", path, err),
+            }
+        }
+        Ok(records)
+    }
+
+    fn save(&self, record: &GameRecord) -> io::Result<()> {
+        let path = self.game_path(&record.game.id);
+        // write to a temporary file first so a crash never leaves a half written game behind
+        let tmp_path = path.with_extension("json.tmp");
+        fs::write(&tmp_path, serde_json::to_vec(record)?)?;
+        fs::rename(tmp_path, path)
+    }
+
+    fn remove(&self, game_id: &GameId) -> io::Result<()> {
+        match fs::remove_file(self.game_path(game_id)) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        }
+    }
+
+    fn archive(&self, record: &GameRecord) -> io::Result<()> {
+        let dir = self.archive_dir();
+        fs::create_dir_all(&dir)?;
+        let path = dir.join(format!("{}.json", record.game.id));
+        fs::write(path, serde_json::to_vec(record)?)?;
+        self.remove(&record.game.id)
+    }
+
+    fn clear(&self) -> io::Result<()> {
+        for path in self.game_files()? {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    // the directory could be gone or read-only, writing to it is the only way to know
+    fn check(&self) -> io::Result<()> {
+        let path = self.dir.join(".ready");
+        fs::write(&path, [])?;
+        fs::remove_file(path)
+    }
+}
+
+fn read_record(path: &Path) -> io::Result<GameRecord> {
+    let data = fs::read(path)?;
+    Ok(serde_json::from_slice(&data)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::{BacklogStory, Deck, Presence, RoundTimer, StoryInfo, User, Vote};
+
+    fn file_store() -> (Arc<FileStore>, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("store-{}", uuid::Uuid::new_v4()));
+        (Arc::new(FileStore::new(&dir).unwrap()), dir)
+    }
+
+    #[tokio::test]
+    async fn writer_keeps_the_order_of_the_changes() {
+        let (store, dir) = file_store();
+        let writer = StoreWriter::spawn(store.clone());
+        let kept = GameRecord::new(User::new("Alice".to_string()), Deck::default());
+        let removed = GameRecord::new(User::new("Bob".to_string()), Deck::default());
+        writer.save(kept.clone());
+        writer.save(removed.clone());
+        writer.remove(removed.game.id);
+        writer.flush().await;
+
+        let ids = store
+            .load()
+            .unwrap()
+            .into_iter()
+            .map(|record| record.game.id)
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec![kept.game.id]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn writer_keeps_the_latest_state() {
+        let (store, dir) = file_store();
+        let writer = StoreWriter::spawn(store.clone());
+        let mut record = GameRecord::new(User::new("Alice".to_string()), Deck::default());
+        let admin_id = record.log.creator.id;
+        writer.save(record.clone());
+        record
+            .apply(admin_id, common::GameAction::AutoRevealChanged(true), 1)
+            .unwrap();
+        writer.save(record.clone());
+        writer.flush().await;
+
+        let loaded = store.load().unwrap();
+        assert_eq!(loaded.len(), 1);
+        assert_eq!(loaded[0].game.seq, record.game.seq);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn replaying_the_log_rebuilds_the_game() {
+        let admin = User::new("Alice".to_string());
+        let player = User::new("Bob".to_string());
+        let (admin_id, player_id) = (admin.id, player.id);
+        let story = BacklogStory::new(StoryInfo::new("Login".to_string()));
+        let story_id = story.id;
+        let mut record = GameRecord::new(admin, Deck::default());
+
+        let actions = [
+            (player_id, GameAction::PlayerJoined(player)),
+            (admin_id, GameAction::StoriesAdded(vec![story])),
+            (admin_id, GameAction::AutoRevealChanged(true)),
+            (admin_id, GameAction::VotingOpened(story_id, 0)),
+            (
+                admin_id,
+                GameAction::TimerStarted(RoundTimer::new(4, 30_000, false)),
+            ),
+            (player_id, GameAction::VoteCasted(Vote::Points(3))),
+            (admin_id, GameAction::VoteCasted(Vote::Points(5))),
+            (admin_id, GameAction::VotesCleared(8)),
+            (player_id, GameAction::PresenceChanged(Presence::Away, 9)),
+            (admin_id, GameAction::VoteCasted(Vote::Unsure)),
+            (player_id, GameAction::VoteCasted(Vote::Points(8))),
+            (
+                admin_id,
+                GameAction::ResultsApproved(Some(Vote::Points(8)), 12),
+            ),
+            (player_id, GameAction::PlayerLeft),
+        ];
+        for (timestamp, (user_id, action)) in (1..).zip(actions) {
+            let events = record.apply(user_id, action, timestamp).unwrap();
+            assert!(!events.is_empty());
+        }
+        // rejected and no-op actions aren't logged
+        let logged = record.log.actions.len();
+        assert_eq!(
+            record.apply(admin_id, GameAction::VoteCasted(Vote::Points(4)), 15),
+            Err(GameError::InvalidVote)
+        );
+        assert_eq!(
+            record.apply(admin_id, GameAction::AutoRevealChanged(true), 16),
+            Ok(vec![])
+        );
+        assert_eq!(record.log.actions.len(), logged);
+
+        assert_eq!(record.game.estimated_stories.len(), 1);
+        assert_eq!(record.game.seq, record.log.actions.len() as u64);
+        assert_eq!(record.log.replay(), record.game);
+    }
+}
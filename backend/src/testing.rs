@@ -0,0 +1,67 @@
+//! Router of the backend for tests, driven without a server.
+
+use axum::{
+    body::Body,
+    http::{header, Method, Request, StatusCode},
+    Router,
+};
+use common::GameCreated;
+use serde_json::{json, Value};
+use std::{sync::Arc, time::Duration};
+use tower::ServiceExt;
+
+use crate::{
+    app,
+    store::GameStore,
+    webhooks::{Dispatcher, RetryPolicy},
+    AppSecret, AppState,
+};
+
+pub(crate) const SECRET: &str = "secret";
+
+pub(crate) fn test_state(store: Arc<dyn GameStore>) -> Arc<AppState> {
+    let webhooks = Dispatcher::new(RetryPolicy::default(), false);
+    Arc::new(AppState::load(store, Duration::from_secs(30), webhooks).unwrap())
+}
+
+pub(crate) fn test_app(store: Arc<dyn GameStore>) -> Router {
+    app(test_state(store), AppSecret(Some(SECRET.to_string())))
+}
+
+/// Sends the request with the bearer token and the json body if there are any.
+pub(crate) async fn call(
+    app: Router,
+    method: Method,
+    uri: &str,
+    token: Option<&str>,
+    body: Option<Value>,
+) -> (StatusCode, Vec<u8>) {
+    let mut request = Request::builder().method(method).uri(uri);
+    if let Some(token) = token {
+        request = request.header(header::AUTHORIZATION, format!("Bearer {token}"));
+    }
+    let body = match body {
+        Some(body) => {
+            request = request.header(header::CONTENT_TYPE, "application/json");
+            Body::from(body.to_string())
+        }
+        None => Body::empty(),
+    };
+    let response = app.oneshot(request.body(body).unwrap()).await.unwrap();
+    let status = response.status();
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    (status, body.to_vec())
+}
+
+pub(crate) async fn get(app: Router, uri: &str) -> (StatusCode, Vec<u8>) {
+    call(app, Method::GET, uri, None, None).await
+}
+
+/// Creates a game and returns it with the session token of its admin.
+pub(crate) async fn create_game(app: Router) -> (GameCreated, String) {
+    let new_game = json!({ "user": { "id": uuid::Uuid::new_v4(), "name": "Alice" } });
+    let (_, body) = call(app, Method::POST, "/api/game", None, Some(new_game)).await;
+    let created: GameCreated = serde_json::from_slice(&body).unwrap();
+    let token = serde_json::to_value(created.token).unwrap();
+    (created, token.as_str().unwrap().to_string())
+}
@@ -0,0 +1,605 @@
+//! Webhooks registered on a game, the server posts them signed notifications and retries
+//! failed deliveries with a backoff.
+
+use axum::{
+    extract::{Extension, Path},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use axum_auth::AuthBearer;
+use common::{
+    report::GameSummary,
+    webhook::{
+        DeliveryId, DeliveryStatus, NewWebhook, Webhook, WebhookData, WebhookDelivery, WebhookId,
+        WebhookPayload,
+    },
+    Game, GameEvent, GameId,
+};
+use hmac::{Hmac, Mac};
+use hyper::client::connect::dns::Name;
+use reqwest::{
+    dns::{Addrs, Resolve, Resolving},
+    redirect, Url,
+};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::{
+    collections::{HashMap, VecDeque},
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::sync::Mutex;
+
+use crate::{admin_by_token, now, AppState};
+
+/// Deliveries kept in the log of every game, the oldest ones go first.
+const MAX_LOGGED_DELIVERIES: usize = 100;
+/// How long a single attempt can take.
+const ATTEMPT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Webhook as kept with its game.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub(crate) struct WebhookRegistration {
+    #[serde(flatten)]
+    pub(crate) hook: Webhook,
+    pub(crate) secret: String,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct RetryPolicy {
+    pub(crate) attempts: u32,
+    /// Wait after the first failed attempt, doubled after each of the next ones.
+    pub(crate) first_delay: Duration,
+    pub(crate) max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            attempts: 6,
+            first_delay: Duration::from_secs(2),
+            max_delay: Duration::from_secs(60),
+        }
+    }
+}
+
+pub(crate) struct Dispatcher {
+    client: reqwest::Client,
+    retry: RetryPolicy,
+    log: Arc<DeliveryLog>,
+    /// Whether webhooks can point to loopback, private and link-local addresses.
+    allow_private: bool,
+}
+
+impl Dispatcher {
+    pub(crate) fn new(retry: RetryPolicy, allow_private: bool) -> Self {
+        // a redirect could lead anywhere, past the checks of the address
+        let mut builder = reqwest::Client::builder()
+            .timeout(ATTEMPT_TIMEOUT)
+            .redirect(redirect::Policy::none());
+        if !allow_private {
+            builder = builder.dns_resolver(Arc::new(PublicResolver));
+        }
+        Dispatcher {
+            client: builder.build().unwrap(),
+            retry,
+            log: Arc::new(DeliveryLog::default()),
+            allow_private,
+        }
+    }
+
+    /// Checks that the server may post to the URL, host names are checked again when resolved.
+    pub(crate) fn check_url(&self, url: &str) -> Result<Url, String> {
+        let url = Url::parse(url).map_err(|err| err.to_string())?;
+        if !["http", "https"].contains(&url.scheme()) {
+            return Err("the URL must be an http or https one".to_string());
+        }
+        let Some(host) = url.host_str() else {
+            return Err("the URL has no host".to_string());
+        };
+        let ip = host
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse::<IpAddr>();
+        match ip {
+            Ok(ip) if !self.allow_private && !is_public(ip) => {
+                Err(format!("{ip} isn't a public address"))
+            }
+            _ => Ok(url),
+        }
+    }
+
+    /// Posts what the events changed to the webhooks that subscribed to it, in the background.
+    pub(crate) fn dispatch(
+        &self,
+        game: &Game,
+        hooks: &[WebhookRegistration],
+        events: &[GameEvent],
+    ) {
+        if hooks.is_empty() {
+            return;
+        }
+        for data in events.iter().flat_map(|event| webhook_data(game, event)) {
+            for hook in hooks.iter().filter(|hook| hook.hook.wants(data.event())) {
+                // registered before the addresses were checked
+                if let Err(err) = self.check_url(&hook.hook.url) {
+                    tracing::warn!("skipping webhook {}: {}", hook.hook.id, err);
+                    continue;
+                }
+                let payload = WebhookPayload {
+                    delivery_id: DeliveryId::random(),
+                    game_id: game.id,
+                    timestamp: now(),
+                    data: data.clone(),
+                };
+                tokio::spawn(deliver(
+                    self.client.clone(),
+                    self.retry,
+                    self.log.clone(),
+                    hook.clone(),
+                    payload,
+                ));
+            }
+        }
+    }
+
+    pub(crate) async fn deliveries(&self, game_id: GameId) -> Vec<WebhookDelivery> {
+        let log = self.log.0.lock().await;
+        log.get(&game_id)
+            .map(|deliveries| deliveries.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    pub(crate) async fn forget(&self, game_id: &GameId) {
+        self.log.0.lock().await.remove(game_id);
+    }
+}
+
+// resolves host names only to public addresses, so webhooks can't reach internal services
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let addrs = tokio::net::lookup_host((name.as_str(), 0))
+                .await?
+                .filter(|addr| is_public(addr.ip()))
+                .collect::<Vec<SocketAddr>>();
+            if addrs.is_empty() {
+                let message = format!("{} doesn't resolve to a public address", name.as_str());
+                return Err(message.into());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Whether the address is reachable from the internet, rather than the host or its network.
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [first, second, ..] = ip.octets();
+            // "this network", the carrier-grade NAT, benchmarking and reserved ranges aren't
+            // covered by the std checks
+            let is_internal = first == 0
+                || (first == 100 && second & 0xc0 == 64)
+                || (first == 198 && second & 0xfe == 18)
+                || first >= 240;
+            !(ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || ip.is_documentation()
+                || is_internal)
+        }
+        IpAddr::V6(ip) => {
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                return is_public(ip.into());
+            }
+            let segments = ip.segments();
+            let first = segments[0];
+            let is_unique_local = first & 0xfe00 == 0xfc00;
+            let is_link_local = first & 0xffc0 == 0xfe80;
+            // NAT64 and the deprecated IPv4-compatible addresses can reach internal IPv4 ones
+            let is_nat64 = segments[..2] == [0x64, 0xff9b];
+            let is_ipv4_compatible = segments[..6] == [0; 6];
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                || is_unique_local
+                || is_link_local
+                || is_nat64
+                || is_ipv4_compatible)
+        }
+    }
+}
+
+#[derive(Default)]
+struct DeliveryLog(Mutex<HashMap<GameId, VecDeque<WebhookDelivery>>>);
+
+impl DeliveryLog {
+    // replaces the previous state of the delivery
+    async fn record(&self, game_id: GameId, delivery: &WebhookDelivery) {
+        let mut log = self.0.lock().await;
+        let deliveries = log.entry(game_id).or_default();
+        if let Some(logged) = deliveries
+            .iter_mut()
+            .find(|logged| logged.id == delivery.id)
+        {
+            *logged = delivery.clone();
+            return;
+        }
+        deliveries.push_back(delivery.clone());
+        if deliveries.len() > MAX_LOGGED_DELIVERIES {
+            deliveries.pop_front();
+        }
+    }
+}
+
+// what the webhooks get to know about the event
+fn webhook_data(game: &Game, event: &GameEvent) -> Vec<WebhookData> {
+    match event {
+        GameEvent::StoriesAdded(story_ids) => {
+            let stories = story_ids
+                .iter()
+                .filter_map(|story_id| game.backlog_stories.get(story_id))
+                .cloned()
+                .collect();
+            vec![WebhookData::StoriesAdded(stories)]
+        }
+        GameEvent::VotingOpened(story_id) => game
+            .selected_story
+            .iter()
+            .filter(|story| story.id == *story_id)
+            .cloned()
+            .map(WebhookData::VotingOpened)
+            .collect(),
+        GameEvent::StoryEstimated(story_id, _) => {
+            let mut data = game
+                .estimated_stories
+                .get(story_id)
+                .cloned()
+                .map(WebhookData::ResultsApproved)
+                .into_iter()
+                .collect::<Vec<_>>();
+            if game.backlog_stories.is_empty() && game.selected_story.is_none() {
+                data.push(WebhookData::GameFinished(GameSummary::new(game)));
+            }
+            data
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Hex encoded HMAC-SHA256 of the body, prefixed with the name of the algorithm.
+pub(crate) fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+// posts the payload until the webhook answers with a success or the attempts run out
+async fn deliver(
+    client: reqwest::Client,
+    retry: RetryPolicy,
+    log: Arc<DeliveryLog>,
+    hook: WebhookRegistration,
+    payload: WebhookPayload,
+) {
+    let body = serde_json::to_vec(&payload).unwrap();
+    let event = payload.data.event();
+    let signature = sign(&hook.secret, &body);
+    let mut delivery = WebhookDelivery {
+        id: payload.delivery_id,
+        webhook_id: hook.hook.id,
+        event,
+        status: DeliveryStatus::Pending,
+        attempts: 0,
+        response_status: None,
+        error: None,
+        created_at: payload.timestamp,
+        updated_at: payload.timestamp,
+    };
+    log.record(payload.game_id, &delivery).await;
+
+    let mut delay = retry.first_delay;
+    loop {
+        delivery.attempts += 1;
+        let result = client
+            .post(&hook.hook.url)
+            .header(header::CONTENT_TYPE.as_str(), "application/json")
+            .header("X-Webhook-Event", event.to_string())
+            .header("X-Webhook-Delivery", delivery.id.to_string())
+            .header("X-Webhook-Signature", &signature)
+            .body(body.clone())
+            .send()
+            .await;
+        match result {
+            Ok(response) if response.status().is_success() => {
+                delivery.status = DeliveryStatus::Delivered;
+                delivery.response_status = Some(response.status().as_u16());
+                delivery.error = None;
+            }
+            Ok(response) => {
+                delivery.response_status = Some(response.status().as_u16());
+                delivery.error = Some(format!("webhook answered {}", response.status()));
+            }
+            Err(err) => {
+                delivery.response_status = None;
+                delivery.error = Some(err.to_string());
+            }
+        }
+        let done =
+            delivery.status == DeliveryStatus::Delivered || delivery.attempts >= retry.attempts;
+        if !done {
+            tracing::debug!(
+                "delivery {} to {} failed, retrying in {:?}",
+                delivery.id,
+                hook.hook.url,
+                delay
+            );
+        } else if delivery.status == DeliveryStatus::Pending {
+            delivery.status = DeliveryStatus::Failed;
+            tracing::warn!("delivery {} to {} failed", delivery.id, hook.hook.url);
+        }
+        delivery.updated_at = now();
+        log.record(payload.game_id, &delivery).await;
+        if done {
+            return;
+        }
+        tokio::time::sleep(delay).await;
+        delay = (delay * 2).min(retry.max_delay);
+    }
+}
+
+pub(crate) async fn list_webhooks(
+    AuthBearer(token): AuthBearer,
+    Path(game_id): Path<GameId>,
+    Extension(state): Extension<Arc<AppState>>,
+) -> Response {
+    if admin_by_token(&state, game_id, &token).await.is_none() {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    let games = state.games.lock().await;
+    let Some(record) = games.get(&game_id) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let hooks = record
+        .webhooks
+        .iter()
+        .map(|registration| registration.hook.clone())
+        .collect::<Vec<_>>();
+    Json(hooks).into_response()
+}
+
+pub(crate) async fn add_webhook(
+    AuthBearer(token): AuthBearer,
+    Path(game_id): Path<GameId>,
+    Extension(state): Extension<Arc<AppState>>,
+    Json(NewWebhook {
+        url,
+        events,
+        secret,
+    }): Json<NewWebhook>,
+) -> Response {
+    if admin_by_token(&state, game_id, &token).await.is_none() {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    if let Err(message) = state.webhooks.check_url(&url) {
+        return (StatusCode::BAD_REQUEST, message).into_response();
+    }
+    if secret.is_empty() {
+        let message = "the secret can't be empty";
+        return (StatusCode::BAD_REQUEST, message).into_response();
+    }
+
+    let mut games = state.games.lock().await;
+    let Some(record) = games.get_mut(&game_id) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let hook = Webhook {
+        id: WebhookId::random(),
+        url,
+        events,
+    };
+    record.webhooks.push(WebhookRegistration {
+        hook: hook.clone(),
+        secret,
+    });
+    state.persist(record);
+    (StatusCode::CREATED, Json(hook)).into_response()
+}
+
+pub(crate) async fn remove_webhook(
+    AuthBearer(token): AuthBearer,
+    Path((game_id, webhook_id)): Path<(GameId, WebhookId)>,
+    Extension(state): Extension<Arc<AppState>>,
+) -> Response {
+    if admin_by_token(&state, game_id, &token).await.is_none() {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    let mut games = state.games.lock().await;
+    let Some(record) = games.get_mut(&game_id) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let count = record.webhooks.len();
+    record
+        .webhooks
+        .retain(|registration| registration.hook.id != webhook_id);
+    if record.webhooks.len() == count {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    state.persist(record);
+    StatusCode::NO_CONTENT.into_response()
+}
+
+// the latest deliveries to every webhook of the game, oldest first
+pub(crate) async fn list_deliveries(
+    AuthBearer(token): AuthBearer,
+    Path(game_id): Path<GameId>,
+    Extension(state): Extension<Arc<AppState>>,
+) -> Response {
+    if admin_by_token(&state, game_id, &token).await.is_none() {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    Json(state.webhooks.deliveries(game_id).await).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Bytes, http::HeaderMap, routing::post, Router};
+    use common::{BacklogStory, StoryInfo, User};
+    use std::{net::SocketAddr, sync::atomic::AtomicUsize, sync::atomic::Ordering};
+    use tokio::sync::mpsc;
+
+    // stand-in for the service behind the webhook, failing as many times as asked to
+    fn stand_in(failures: usize) -> (String, mpsc::UnboundedReceiver<(HeaderMap, Bytes)>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let app = Router::new().route(
+            "/hook",
+            post(move |headers: HeaderMap, body: Bytes| async move {
+                tx.send((headers, body)).unwrap();
+                if calls.fetch_add(1, Ordering::Relaxed) < failures {
+                    StatusCode::SERVICE_UNAVAILABLE
+                } else {
+                    StatusCode::OK
+                }
+            }),
+        );
+        let server = axum::Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0)))
+            .serve(app.into_make_service());
+        let url = format!("http://{}/hook", server.local_addr());
+        tokio::spawn(server);
+        (url, rx)
+    }
+
+    // the stand-in listens on the loopback address
+    fn dispatcher(attempts: u32, allow_private: bool) -> Dispatcher {
+        let retry = RetryPolicy {
+            attempts,
+            first_delay: Duration::from_millis(10),
+            max_delay: Duration::from_millis(20),
+        };
+        Dispatcher::new(retry, allow_private)
+    }
+
+    fn registration(url: String) -> WebhookRegistration {
+        WebhookRegistration {
+            hook: Webhook {
+                id: WebhookId::random(),
+                url,
+                events: Vec::new(),
+            },
+            secret: "secret".to_string(),
+        }
+    }
+
+    fn game_with_story() -> (Game, GameEvent) {
+        let mut game = Game::new(User::new("Alice".to_string()), common::Deck::default());
+        let story = BacklogStory::new(StoryInfo::new("Login page".to_string()));
+        let event = GameEvent::StoriesAdded(vec![story.id]);
+        game.backlog_stories.insert(story.id, story);
+        (game, event)
+    }
+
+    async fn wait_for_status(dispatcher: &Dispatcher, game_id: GameId) -> WebhookDelivery {
+        loop {
+            if let Some(delivery) = dispatcher.deliveries(game_id).await.pop() {
+                if delivery.status != DeliveryStatus::Pending {
+                    return delivery;
+                }
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn delivers_signed_payload_after_retrying() {
+        let (url, mut requests) = stand_in(2);
+        let dispatcher = dispatcher(5, true);
+        let (game, event) = game_with_story();
+        dispatcher.dispatch(&game, &[registration(url)], &[event]);
+
+        let delivery = wait_for_status(&dispatcher, game.id).await;
+        assert_eq!(delivery.status, DeliveryStatus::Delivered);
+        assert_eq!(delivery.attempts, 3);
+        assert_eq!(delivery.response_status, Some(200));
+
+        let (headers, body) = requests.recv().await.unwrap();
+        assert_eq!(headers["X-Webhook-Event"], "stories_added");
+        assert_eq!(
+            headers["X-Webhook-Signature"],
+            sign("secret", &body).as_str()
+        );
+        let payload: WebhookPayload = serde_json::from_slice(&body).unwrap();
+        assert_eq!(payload.delivery_id, delivery.id);
+        assert!(matches!(payload.data, WebhookData::StoriesAdded(stories) if stories.len() == 1));
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_the_last_attempt() {
+        let (url, _requests) = stand_in(usize::MAX);
+        let dispatcher = dispatcher(2, true);
+        let (game, event) = game_with_story();
+        dispatcher.dispatch(&game, &[registration(url)], &[event]);
+
+        let delivery = wait_for_status(&dispatcher, game.id).await;
+        assert_eq!(delivery.status, DeliveryStatus::Failed);
+        assert_eq!(delivery.attempts, 2);
+        assert_eq!(delivery.response_status, Some(503));
+    }
+
+    #[test]
+    fn only_public_addresses_are_allowed() {
+        let public_only = dispatcher(1, false);
+        for url in [
+            "http://127.0.0.1/hook",
+            "http://10.1.2.3/hook",
+            "http://192.168.0.1/hook",
+            "http://169.254.169.254/latest/meta-data",
+            "http://100.64.0.1/hook",
+            "http://[::1]/hook",
+            "http://[fd00:ec2::254]/hook",
+            "http://[::ffff:127.0.0.1]/hook",
+            "http://198.18.0.1/hook",
+            "http://198.19.255.254/hook",
+            "http://240.0.0.1/hook",
+            "http://255.255.255.255/hook",
+            "http://[64:ff9b::a9fe:a9fe]/hook",
+            "http://[64:ff9b:1::a00:1]/hook",
+            "http://[::10.0.0.1]/hook",
+            "ftp://example.com/hook",
+        ] {
+            assert!(public_only.check_url(url).is_err(), "{url}");
+        }
+        assert!(public_only.check_url("https://93.184.216.34/hook").is_ok());
+        assert!(public_only.check_url("https://198.20.0.1/hook").is_ok());
+        assert!(public_only
+            .check_url("https://[2606:2800:220:1::248]/hook")
+            .is_ok());
+        assert!(public_only.check_url("https://example.com/hook").is_ok());
+        assert!(dispatcher(1, true)
+            .check_url("http://127.0.0.1/hook")
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn host_names_resolving_to_private_addresses_are_refused() {
+        let (url, mut requests) = stand_in(0);
+        let url = url.replace("127.0.0.1", "localhost");
+        let dispatcher = dispatcher(1, false);
+        let (game, event) = game_with_story();
+        dispatcher.dispatch(&game, &[registration(url)], &[event]);
+
+        let delivery = wait_for_status(&dispatcher, game.id).await;
+        assert_eq!(delivery.status, DeliveryStatus::Failed);
+        assert_eq!(delivery.response_status, None);
+        assert!(requests.try_recv().is_err());
+    }
+}
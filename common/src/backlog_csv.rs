@@ -0,0 +1,264 @@
+//! Import of backlog stories from and export of estimates to CSV spreadsheets.
+
+use crate::{BacklogStory, Deck, EstimatedStory, StoryId, StoryInfo};
+use indexmap::IndexMap;
+use std::borrow::Cow;
+
+/// Names of the CSV columns the story fields are read from, compared ignoring case.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct CsvColumns {
+    pub title: String,
+    pub key: Option<String>,
+    pub description: Option<String>,
+    pub url: Option<String>,
+    pub labels: Option<String>,
+    pub assignee: Option<String>,
+}
+
+impl Default for CsvColumns {
+    fn default() -> Self {
+        CsvColumns {
+            title: "title".to_string(),
+            key: Some("key".to_string()),
+            description: Some("description".to_string()),
+            url: Some("link".to_string()),
+            labels: Some("labels".to_string()),
+            assignee: Some("assignee".to_string()),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Debug, derive_more::Display)]
+pub enum CsvError {
+    #[display(fmt = "missing column: {_0}")]
+    MissingColumn(String),
+    #[display(fmt = "invalid csv: {_0}")]
+    Invalid(String),
+}
+
+impl From<csv::Error> for CsvError {
+    fn from(err: csv::Error) -> Self {
+        CsvError::Invalid(err.to_string())
+    }
+}
+
+/// Reads a story from every row with a non empty title.
+pub fn parse_stories(data: &str, columns: &CsvColumns) -> Result<Vec<BacklogStory>, CsvError> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(data.as_bytes());
+    let headers = reader.headers()?.clone();
+    let find = |name: &str| {
+        headers
+            .iter()
+            .position(|header| header.eq_ignore_ascii_case(name))
+    };
+    let title_idx =
+        find(&columns.title).ok_or_else(|| CsvError::MissingColumn(columns.title.clone()))?;
+    // optional columns are simply left out when the file doesn't have them
+    let key_idx = columns.key.as_deref().and_then(find);
+    let description_idx = columns.description.as_deref().and_then(find);
+    let url_idx = columns.url.as_deref().and_then(find);
+    let labels_idx = columns.labels.as_deref().and_then(find);
+    let assignee_idx = columns.assignee.as_deref().and_then(find);
+
+    let mut stories = Vec::new();
+    for record in reader.records() {
+        let record = record?;
+        let field = |idx: Option<usize>| {
+            idx.and_then(|idx| record.get(idx))
+                .filter(|value| !value.is_empty())
+                .map(ToString::to_string)
+        };
+        let Some(title) = field(Some(title_idx)) else {
+            continue;
+        };
+        let labels = field(labels_idx)
+            .map(|labels| split_labels(&labels))
+            .unwrap_or_default();
+        stories.push(BacklogStory::new(StoryInfo {
+            title,
+            key: field(key_idx),
+            description: field(description_idx),
+            url: field(url_idx),
+            labels,
+            assignee: field(assignee_idx),
+        }));
+    }
+    Ok(stories)
+}
+
+/// Writes the estimated stories with the labels of their estimates and the numeric values.
+pub fn write_estimates(
+    stories: &IndexMap<StoryId, EstimatedStory>,
+    deck: &Deck,
+) -> Result<String, CsvError> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record([
+        "key",
+        "title",
+        "description",
+        "link",
+        "labels",
+        "assignee",
+        "estimate",
+        "points",
+    ])?;
+    for story in stories.values() {
+        let info = &story.info;
+        writer.write_record([
+            &*text_cell(info.key.as_deref().unwrap_or_default()),
+            &*text_cell(&info.title),
+            &*text_cell(info.description.as_deref().unwrap_or_default()),
+            &*text_cell(info.url.as_deref().unwrap_or_default()),
+            &*text_cell(&info.labels.join(", ")),
+            &*text_cell(info.assignee.as_deref().unwrap_or_default()),
+            &deck.label(story.estimate),
+            &story
+                .estimate
+                .value()
+                .map(|value| value.to_string())
+                .unwrap_or_default(),
+        ])?;
+    }
+    let data = writer
+        .into_inner()
+        .map_err(|err| CsvError::Invalid(err.to_string()))?;
+    String::from_utf8(data).map_err(|err| CsvError::Invalid(err.to_string()))
+}
+
+// spreadsheets run cells starting like a formula, the quote makes them plain text
+fn text_cell(text: &str) -> Cow<'_, str> {
+    if text.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        Cow::Owned(format!("'{text}"))
+    } else {
+        Cow::Borrowed(text)
+    }
+}
+
+fn split_labels(labels: &str) -> Vec<String> {
+    labels
+        .split(',')
+        .map(str::trim)
+        .filter(|label| !label.is_empty())
+        .map(ToString::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Vote;
+
+    fn infos(stories: Vec<BacklogStory>) -> Vec<StoryInfo> {
+        stories.into_iter().map(|story| story.info).collect()
+    }
+
+    #[test]
+    fn headers_are_matched_ignoring_case() {
+        let data = "Summary,KEY,Labels\nLogin,POK-1,\"auth, security ,,\"\n";
+        let columns = CsvColumns {
+            title: "summary".to_string(),
+            ..CsvColumns::default()
+        };
+        assert_eq!(
+            infos(parse_stories(data, &columns).unwrap()),
+            vec![StoryInfo {
+                title: "Login".to_string(),
+                key: Some("POK-1".to_string()),
+                labels: vec!["auth".to_string(), "security".to_string()],
+                ..StoryInfo::default()
+            }]
+        );
+    }
+
+    #[test]
+    fn the_title_column_is_required() {
+        assert_eq!(
+            parse_stories("name\nLogin\n", &CsvColumns::default()),
+            Err(CsvError::MissingColumn("title".to_string()))
+        );
+    }
+
+    #[test]
+    fn rows_without_a_title_are_skipped() {
+        let data = "title,assignee\nLogin,Alice\n  ,Bob\n\nLogout\n";
+        let titles = parse_stories(data, &CsvColumns::default())
+            .unwrap()
+            .into_iter()
+            .map(|story| story.info.title)
+            .collect::<Vec<_>>();
+        assert_eq!(titles, ["Login", "Logout"]);
+    }
+
+    #[test]
+    fn exported_formulas_are_plain_text() {
+        let info = StoryInfo {
+            title: "=HYPERLINK(\"https://example.com\")".to_string(),
+            labels: vec!["+1".to_string()],
+            assignee: Some("@alice".to_string()),
+            description: Some("- a list".to_string()),
+            ..StoryInfo::new(String::new())
+        };
+        let story = BacklogStory::new(info)
+            .select_for_estimation()
+            .accept_with_estimate(Vote::Points(3), 0);
+        let stories = [(story.id, story)].into_iter().collect();
+
+        let data = write_estimates(&stories, &Deck::default()).unwrap();
+        let mut reader = csv::Reader::from_reader(data.as_bytes());
+        let record = reader.records().next().unwrap().unwrap();
+        assert_eq!(&record[1], "'=HYPERLINK(\"https://example.com\")");
+        assert_eq!(&record[2], "'- a list");
+        assert_eq!(&record[4], "'+1");
+        assert_eq!(&record[5], "'@alice");
+        assert_eq!(&record[7], "3");
+    }
+
+    #[test]
+    fn exported_estimates_can_be_imported_again() {
+        let login = StoryInfo {
+            title: "Login".to_string(),
+            key: Some("POK-1".to_string()),
+            description: Some("With a password, or \"magic\" links".to_string()),
+            url: Some("https://example.com/POK-1".to_string()),
+            labels: vec!["auth".to_string(), "security".to_string()],
+            assignee: Some("Alice".to_string()),
+        };
+        let logout = StoryInfo::new("Logout".to_string());
+        let stories = [
+            (login.clone(), Vote::Points(5)),
+            (logout.clone(), Vote::Unsure),
+        ]
+        .into_iter()
+        .map(|(info, estimate)| {
+            let story = BacklogStory::new(info)
+                .select_for_estimation()
+                .accept_with_estimate(estimate, 0);
+            (story.id, story)
+        })
+        .collect();
+
+        let data = write_estimates(&stories, &Deck::TShirtSizes).unwrap();
+        let mut reader = csv::Reader::from_reader(data.as_bytes());
+        let estimates = reader
+            .records()
+            .map(|record| {
+                let record = record.unwrap();
+                (record[6].to_string(), record[7].to_string())
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            estimates,
+            [
+                ("L".to_string(), "5".to_string()),
+                ("?".to_string(), String::new())
+            ]
+        );
+        assert_eq!(
+            infos(parse_stories(&data, &CsvColumns::default()).unwrap()),
+            vec![login, logout]
+        );
+    }
+}
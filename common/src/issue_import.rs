@@ -0,0 +1,424 @@
+//! Import of backlog stories from issues saved from Jira or GitHub.
+
+use crate::{BacklogStory, StoryInfo};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Format of the saved issues.
+#[derive(PartialEq, Eq, Clone, Copy, Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportFormat {
+    /// Response of the Jira search REST API.
+    JiraJson,
+    /// RSS document of the Jira issue navigator "Export XML" action.
+    JiraXml,
+    /// Response of the GitHub issues REST API.
+    GithubJson,
+}
+
+impl ImportFormat {
+    /// Guesses the format from the content of the file.
+    pub fn detect(data: &str) -> Option<ImportFormat> {
+        let data = data.trim_start();
+        if data.starts_with('<') {
+            Some(ImportFormat::JiraXml)
+        } else if data.starts_with('[') {
+            Some(ImportFormat::GithubJson)
+        } else if data.starts_with('{') {
+            Some(ImportFormat::JiraJson)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Debug, derive_more::Display)]
+pub enum ImportError {
+    #[display(fmt = "unknown format")]
+    UnknownFormat,
+    #[display(fmt = "invalid json: {_0}")]
+    InvalidJson(String),
+    #[display(fmt = "invalid xml: {_0}")]
+    InvalidXml(String),
+}
+
+pub fn import_stories(format: ImportFormat, data: &str) -> Result<Vec<BacklogStory>, ImportError> {
+    let infos = match format {
+        ImportFormat::JiraJson => jira_json(data)?,
+        ImportFormat::JiraXml => jira_xml(data)?,
+        ImportFormat::GithubJson => github_json(data)?,
+    };
+    Ok(infos
+        .into_iter()
+        .filter(|info| !info.title.trim().is_empty())
+        .map(BacklogStory::new)
+        .collect())
+}
+
+#[derive(Deserialize)]
+struct JiraSearch {
+    issues: Vec<JiraIssue>,
+}
+
+#[derive(Deserialize)]
+struct JiraIssue {
+    key: String,
+    /// REST url of the issue, the browse url is derived from it.
+    #[serde(rename = "self")]
+    api_url: Option<String>,
+    fields: JiraFields,
+}
+
+#[derive(Deserialize)]
+struct JiraFields {
+    summary: String,
+    /// Wiki markup in the v2 API, a document tree in the v3 API.
+    #[serde(default)]
+    description: Value,
+    #[serde(default)]
+    labels: Vec<String>,
+    assignee: Option<JiraUser>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JiraUser {
+    display_name: String,
+}
+
+fn jira_json(data: &str) -> Result<Vec<StoryInfo>, ImportError> {
+    let search: JiraSearch =
+        serde_json::from_str(data).map_err(|err| ImportError::InvalidJson(err.to_string()))?;
+    Ok(search
+        .issues
+        .into_iter()
+        .map(|issue| {
+            let url = issue.api_url.as_deref().and_then(|api_url| {
+                let (base, _) = api_url.split_once("/rest/")?;
+                Some(format!("{base}/browse/{}", issue.key))
+            });
+            let description = match issue.fields.description {
+                Value::String(text) => Some(text),
+                Value::Null => None,
+                document => Some(document_text(&document)),
+            };
+            StoryInfo {
+                title: issue.fields.summary,
+                key: Some(issue.key),
+                description: description.filter(|text| !text.trim().is_empty()),
+                url,
+                labels: issue.fields.labels,
+                assignee: issue.fields.assignee.map(|user| user.display_name),
+            }
+        })
+        .collect())
+}
+
+// plain text of an Atlassian document, every block on its own line
+fn document_text(node: &Value) -> String {
+    if let Some(text) = node.get("text").and_then(Value::as_str) {
+        return text.to_string();
+    }
+    let Some(content) = node.get("content").and_then(Value::as_array) else {
+        return String::new();
+    };
+    let is_inline = content
+        .iter()
+        .all(|child| child.get("type").and_then(Value::as_str) == Some("text"));
+    let separator = if is_inline { "" } else { "\n\n" };
+    content
+        .iter()
+        .map(document_text)
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+#[derive(Deserialize)]
+struct JiraRss {
+    channel: JiraChannel,
+}
+
+#[derive(Deserialize)]
+struct JiraChannel {
+    #[serde(default, rename = "item")]
+    items: Vec<JiraItem>,
+}
+
+#[derive(Deserialize)]
+struct JiraItem {
+    title: String,
+    link: Option<String>,
+    key: Option<XmlText>,
+    summary: Option<String>,
+    description: Option<String>,
+    #[serde(default)]
+    labels: JiraLabels,
+    assignee: Option<XmlText>,
+}
+
+#[derive(Deserialize, Default)]
+struct JiraLabels {
+    #[serde(default, rename = "label")]
+    labels: Vec<String>,
+}
+
+/// Text of an element that has attributes as well.
+#[derive(Deserialize)]
+struct XmlText {
+    #[serde(default, rename = "$text")]
+    text: String,
+}
+
+fn jira_xml(data: &str) -> Result<Vec<StoryInfo>, ImportError> {
+    let rss: JiraRss =
+        quick_xml::de::from_str(data).map_err(|err| ImportError::InvalidXml(err.to_string()))?;
+    Ok(rss
+        .channel
+        .items
+        .into_iter()
+        .map(|item| {
+            let key = item.key.map(|key| key.text);
+            // the title is prefixed with the key, the summary holds only the title
+            let title = item.summary.unwrap_or_else(|| match &key {
+                Some(key) => item
+                    .title
+                    .trim_start_matches(&format!("[{key}]"))
+                    .trim()
+                    .to_string(),
+                None => item.title,
+            });
+            StoryInfo {
+                title,
+                key,
+                description: item
+                    .description
+                    .map(|html| strip_html(&html))
+                    .filter(|text| !text.is_empty()),
+                url: item.link,
+                labels: item.labels.labels,
+                assignee: item
+                    .assignee
+                    .map(|assignee| assignee.text)
+                    .filter(|name| name != "Unassigned"),
+            }
+        })
+        .collect())
+}
+
+// Jira exports descriptions as html, only the text and line breaks are kept
+fn strip_html(html: &str) -> String {
+    let mut text = String::new();
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        text.push_str(&rest[..start]);
+        let Some(end) = rest[start..].find('>') else {
+            rest = "";
+            break;
+        };
+        let tag = rest[start + 1..start + end]
+            .trim_start_matches('/')
+            .to_lowercase();
+        if ["br", "p", "li", "div"]
+            .iter()
+            .any(|name| tag.split_whitespace().next() == Some(*name))
+        {
+            text.push('\n');
+        }
+        rest = &rest[start + end + 1..];
+    }
+    text.push_str(rest);
+    let text = text
+        .replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&");
+    text.lines()
+        .map(str::trim)
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim()
+        .to_string()
+}
+
+#[derive(Deserialize)]
+struct GithubIssue {
+    number: u64,
+    title: String,
+    body: Option<String>,
+    html_url: Option<String>,
+    #[serde(default)]
+    labels: Vec<GithubLabel>,
+    assignee: Option<GithubUser>,
+    /// Only set for pull requests, which the issues API returns as well.
+    pull_request: Option<Value>,
+}
+
+#[derive(Deserialize)]
+struct GithubLabel {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct GithubUser {
+    login: String,
+}
+
+fn github_json(data: &str) -> Result<Vec<StoryInfo>, ImportError> {
+    let issues: Vec<GithubIssue> =
+        serde_json::from_str(data).map_err(|err| ImportError::InvalidJson(err.to_string()))?;
+    Ok(issues
+        .into_iter()
+        .filter(|issue| issue.pull_request.is_none())
+        .map(|issue| StoryInfo {
+            title: issue.title,
+            key: Some(format!("#{}", issue.number)),
+            description: issue.body.filter(|body| !body.trim().is_empty()),
+            url: issue.html_url,
+            labels: issue.labels.into_iter().map(|label| label.name).collect(),
+            assignee: issue.assignee.map(|user| user.login),
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn import(format: ImportFormat, data: &str) -> Vec<StoryInfo> {
+        import_stories(format, data)
+            .unwrap()
+            .into_iter()
+            .map(|story| story.info)
+            .collect()
+    }
+
+    #[test]
+    fn jira_v2_descriptions_are_kept_as_they_are() {
+        let data = r#"{"issues": [{
+            "key": "POK-1",
+            "self": "https://example.atlassian.net/rest/api/2/issue/10001",
+            "fields": {
+                "summary": "Login",
+                "description": "Users *log in* with a password",
+                "labels": ["auth"],
+                "assignee": {"displayName": "Alice"}
+            }
+        }]}"#;
+        assert_eq!(
+            import(ImportFormat::JiraJson, data),
+            vec![StoryInfo {
+                title: "Login".to_string(),
+                key: Some("POK-1".to_string()),
+                description: Some("Users *log in* with a password".to_string()),
+                url: Some("https://example.atlassian.net/browse/POK-1".to_string()),
+                labels: vec!["auth".to_string()],
+                assignee: Some("Alice".to_string()),
+            }]
+        );
+    }
+
+    #[test]
+    fn jira_v3_documents_become_text() {
+        let data = r#"{"issues": [{
+            "key": "POK-2",
+            "fields": {
+                "summary": "Logout",
+                "description": {"type": "doc", "version": 1, "content": [
+                    {"type": "paragraph", "content": [
+                        {"type": "text", "text": "Ends the "},
+                        {"type": "text", "text": "session"}
+                    ]},
+                    {"type": "paragraph", "content": [{"type": "text", "text": "Clears cookies"}]}
+                ]},
+                "assignee": null
+            }
+        }]}"#;
+        let infos = import(ImportFormat::JiraJson, data);
+        assert_eq!(
+            infos[0].description.as_deref(),
+            Some("Ends the session\n\nClears cookies")
+        );
+        assert_eq!(infos[0].url, None);
+        assert_eq!(infos[0].assignee, None);
+    }
+
+    #[test]
+    fn jira_xml_titles_lose_the_key() {
+        let data = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <rss version="0.92">
+                <channel>
+                    <title>Jira</title>
+                    <item>
+                        <title>[POK-3] Reset the password</title>
+                        <link>https://example.atlassian.net/browse/POK-3</link>
+                        <key id="10003">POK-3</key>
+                        <description>&lt;p&gt;Sends an &lt;b&gt;email&lt;/b&gt;&lt;/p&gt;</description>
+                        <labels><label>auth</label><label>email</label></labels>
+                        <assignee username="-1">Unassigned</assignee>
+                    </item>
+                </channel>
+            </rss>"#;
+        assert_eq!(
+            import(ImportFormat::JiraXml, data),
+            vec![StoryInfo {
+                title: "Reset the password".to_string(),
+                key: Some("POK-3".to_string()),
+                description: Some("Sends an email".to_string()),
+                url: Some("https://example.atlassian.net/browse/POK-3".to_string()),
+                labels: vec!["auth".to_string(), "email".to_string()],
+                assignee: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn github_pull_requests_are_skipped() {
+        let data = r#"[
+            {
+                "number": 7,
+                "title": "Dark mode",
+                "body": "",
+                "html_url": "https://github.com/example/poker/issues/7",
+                "labels": [{"name": "ui"}],
+                "assignee": {"login": "bob"}
+            },
+            {
+                "number": 8,
+                "title": "Add dark mode",
+                "body": "Fixes #7",
+                "pull_request": {"url": "https://api.github.com/repos/example/poker/pulls/8"}
+            }
+        ]"#;
+        assert_eq!(
+            import(ImportFormat::GithubJson, data),
+            vec![StoryInfo {
+                title: "Dark mode".to_string(),
+                key: Some("#7".to_string()),
+                description: None,
+                url: Some("https://github.com/example/poker/issues/7".to_string()),
+                labels: vec!["ui".to_string()],
+                assignee: Some("bob".to_string()),
+            }]
+        );
+    }
+
+    #[test]
+    fn formats_are_detected_from_the_content() {
+        assert_eq!(
+            ImportFormat::detect("  <?xml version=\"1.0\"?><rss/>"),
+            Some(ImportFormat::JiraXml)
+        );
+        assert_eq!(
+            ImportFormat::detect("\n[{\"number\": 1}]"),
+            Some(ImportFormat::GithubJson)
+        );
+        assert_eq!(
+            ImportFormat::detect("{\"issues\": []}"),
+            Some(ImportFormat::JiraJson)
+        );
+        assert_eq!(ImportFormat::detect("title,points"), None);
+    }
+}
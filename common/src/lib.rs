@@ -14,12 +14,20 @@
     clippy::must_use_candidate
 )]
 
+pub mod backlog_csv;
+pub mod issue_import;
+pub mod links;
+pub mod report;
+pub mod schema;
+pub mod webhook;
+
 use indexmap::IndexMap;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::str::FromStr;
 use uuid::Uuid;
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
 pub enum GameAction {
     PlayerJoined(User),
     PlayerLeft,
@@ -27,23 +35,197 @@ pub enum GameAction {
     StoryUpdated(StoryId, StoryInfo),
     StoryPositionChanged(StoryId, usize),
     StoryRemoved(StoryId),
-    VotingOpened(StoryId),
-    VotingClosed,
+    /// Selects the story for estimation, the server sets when the round of the previous one ended.
+    VotingOpened(StoryId, Timestamp),
+    /// Puts the selected story back into the backlog, the server sets when its round ended.
+    VotingClosed(Timestamp),
     VoteCasted(Vote),
     VotesRevealed,
-    VotesCleared,
-    ResultsApproved(Option<Vote>),
+    /// Starts a new round, the server sets the time the previous one ended.
+    VotesCleared(Timestamp),
+    /// Accepts the story with the given or computed estimate, the server sets the time.
+    ResultsApproved(Option<Vote>, Timestamp),
+    PlayerRoleChanged(UserId, PlayerRole),
+    AdminTransferred(UserId),
+    /// Makes the player an admin, allowed only when none of the admins is active.
+    AdminClaimed,
+    /// Turns revealing the votes as soon as every active voter has voted on or off.
+    AutoRevealChanged(bool),
+    /// Starts a countdown for the selected story, the server sets the timestamps.
+    TimerStarted(RoundTimer),
+    TimerStopped,
+    /// Issued by the server when the timer ending at the given time runs out.
+    TimerExpired(Timestamp),
+    /// Sent by the client when the player comes and goes, the server sets the time.
+    PresenceChanged(Presence, Timestamp),
+}
+
+/// Version of the websocket protocol, bumped on every incompatible change of the messages.
+pub const PROTOCOL_VERSION: u32 = 3;
+
+/// Build of the server, for clients to tell whether they are out of date.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema, Debug)]
+pub struct VersionInfo {
+    /// Version of the crates, the same for the server and the clients it serves.
+    pub version: String,
+    /// Commit the server was built from, `unknown` outside of a git checkout.
+    pub git_sha: String,
+    pub protocol_version: u32,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
 #[allow(clippy::large_enum_variant)]
 pub enum AppEvent {
+    /// First message of both sides of a connection, carrying their protocol version.
+    Hello(u32),
+    /// Sent by the server after its `Hello`, lets clients count down with the clock of the server.
+    ServerTime(Timestamp),
     CurrentState(Game),
     GameNotFound(GameId),
+    /// Sent by the client to play an action in the game.
     GameMessage(UserId, GameAction),
+    /// Sent by the server for every action that changed the game, with the sequence
+    /// number the game got after applying it.
+    GameUpdate(u64, UserId, GameAction),
+    /// Sent by the client that missed an update, the server replies with the current state.
+    ResyncRequested,
+    /// Sent by the client to bind its connection to a user. The token has to be
+    /// provided if the server already issued one for this user in this game.
+    JoinGame(User, Option<SessionToken>),
+    /// Sent by the server to the client whose connection got bound to a user.
+    SessionStarted(SessionToken),
+    /// Sent by the server when the provided token doesn't match the user.
+    SessionRejected,
+    /// Sent by the server to the client whose message couldn't be processed.
+    Error {
+        code: ErrorCode,
+        message: String,
+        /// Number of the rejected message on the connection, counting from 1.
+        request_id: Option<u64>,
+    },
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Serialize, Deserialize, JsonSchema, Debug)]
+pub enum ErrorCode {
+    /// The message isn't a valid event.
+    InvalidMessage,
+    /// The client speaks a different version of the protocol.
+    UnsupportedProtocol,
+    /// The client has to send its protocol version first.
+    HandshakeRequired,
+    /// The client has to join the game first.
+    NotJoined,
+    /// The game rejected the action.
+    ActionRejected(GameError),
+}
+
+/// Reason why a game action was rejected.
+#[derive(
+    PartialEq, Eq, Clone, Copy, Serialize, Deserialize, JsonSchema, Debug, derive_more::Display,
+)]
+pub enum GameError {
+    #[display(fmt = "the user isn't a player of the game")]
+    NotAPlayer,
+    #[display(fmt = "only admins can do this")]
+    NotAdmin,
+    #[display(fmt = "observers can't vote")]
+    CannotVote,
+    #[display(fmt = "an admin is still active")]
+    AdminActive,
+    #[display(fmt = "the player doesn't exist")]
+    UnknownPlayer,
+    #[display(fmt = "the game needs at least one admin")]
+    LastAdmin,
+    #[display(fmt = "the story isn't in the backlog")]
+    UnknownStory,
+    #[display(fmt = "the position is out of the backlog")]
+    IndexOutOfRange,
+    #[display(fmt = "no story is open for voting")]
+    NoStorySelected,
+    #[display(fmt = "the vote isn't in the deck")]
+    InvalidVote,
+    #[display(fmt = "the timer can't run for longer than an hour")]
+    TimerTooLong,
+    #[display(fmt = "the votes haven't been revealed yet")]
+    VotesNotRevealed,
+    #[display(fmt = "the votes have been revealed already")]
+    VotesAlreadyRevealed,
+    #[display(fmt = "nobody has voted yet")]
+    NoVotes,
+    #[display(fmt = "the votes don't give an estimate")]
+    NoEstimate,
+}
+
+/// What an applied game action changed.
+#[derive(PartialEq, Eq, Clone, Serialize, Deserialize, JsonSchema, Debug)]
+pub enum GameEvent {
+    PlayerJoined(UserId),
+    PlayerLeft(UserId),
+    RoleChanged(UserId, PlayerRole),
+    StoriesAdded(Vec<StoryId>),
+    StoryUpdated(StoryId),
+    StoryMoved(StoryId, usize),
+    StoryRemoved(StoryId),
+    VotingOpened(StoryId),
+    /// The story was moved back to the backlog.
+    VotingClosed(StoryId),
+    VoteCasted(UserId),
+    VotesRevealed(StoryId),
+    VotesCleared(StoryId),
+    StoryEstimated(StoryId, Vote),
+    AutoRevealChanged(bool),
+    TimerStarted(RoundTimer),
+    TimerStopped,
+    TimerExpired,
+    PresenceChanged(UserId, Presence),
+}
+
+/// Body of the game creation request.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema, Debug)]
+pub struct NewGame {
+    pub user: User,
+    #[serde(default)]
+    pub deck: Deck,
+}
+
+/// Response of the game creation request.
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema, Debug)]
+pub struct GameCreated {
+    pub id: GameId,
+    /// Session token of the admin who created the game.
+    pub token: SessionToken,
+}
+
+/// Secret issued by the server to prove the identity of a user within a game.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema, Debug)]
+pub struct SessionToken(Uuid);
+
+impl SessionToken {
+    pub fn random() -> Self {
+        Self(Uuid::new_v4())
+    }
+}
+
+impl FromStr for SessionToken {
+    type Err = <Uuid as FromStr>::Err;
+
+    fn from_str(token_str: &str) -> Result<Self, Self::Err> {
+        Uuid::parse_str(token_str).map(Self)
+    }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Debug, derive_more::Display)]
+#[derive(
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Hash,
+    Serialize,
+    Deserialize,
+    JsonSchema,
+    Debug,
+    derive_more::Display,
+)]
 pub struct GameId(Uuid);
 
 impl GameId {
@@ -60,22 +242,36 @@ impl FromStr for GameId {
     }
 }
 
-#[derive(Clone, Serialize, Deserialize, Debug)]
+#[derive(PartialEq, Eq, Clone, Serialize, Deserialize, JsonSchema, Debug)]
 pub struct Game {
     pub id: GameId,
     pub players: IndexMap<UserId, Player>,
     pub backlog_stories: IndexMap<StoryId, BacklogStory>,
     pub estimated_stories: IndexMap<StoryId, EstimatedStory>,
     pub selected_story: Option<SelectedStory>,
+    #[serde(default)]
+    pub deck: Deck,
+    #[serde(default)]
+    pub auto_reveal: bool,
+    /// Number of changes applied to the game, lets clients notice missed updates.
+    #[serde(default)]
+    pub seq: u64,
 }
 
 impl Game {
-    pub fn new(user: User) -> Self {
+    pub fn new(user: User, deck: Deck) -> Self {
+        Game::with_id(GameId(Uuid::new_v4()), user, deck)
+    }
+
+    pub fn with_id(id: GameId, user: User, deck: Deck) -> Self {
         let player = Player::new_admin(user);
         let players = [(player.user.id, player)].into_iter().collect();
 
         Game {
-            id: GameId(Uuid::new_v4()),
+            id,
+            deck,
+            auto_reveal: false,
+            seq: 0,
             backlog_stories: IndexMap::new(),
             estimated_stories: IndexMap::new(),
             selected_story: None,
@@ -87,6 +283,12 @@ impl Game {
         matches!(self.players.get(user_id), Some(player) if player.role == PlayerRole::Admin)
     }
 
+    pub fn has_active_admin(&self) -> bool {
+        self.players
+            .values()
+            .any(|player| player.active && player.role == PlayerRole::Admin)
+    }
+
     pub fn to_active_players(&self) -> IndexMap<UserId, Player> {
         self.players
             .iter()
@@ -94,155 +296,560 @@ impl Game {
             .collect()
     }
 
-    pub fn update(&mut self, user_id: UserId, action: GameAction) {
-        if let GameAction::PlayerJoined(user) = action {
-            self.add_player(user);
-        } else if let Some(player) = self.players.get(&user_id) {
-            let is_admin = player.role == PlayerRole::Admin;
-            match action {
-                GameAction::StoriesAdded(stories) if is_admin => self.add_stories(stories),
-                GameAction::StoryUpdated(story_id, story_info) if is_admin => {
-                    self.update_story(story_id, story_info);
-                }
-                GameAction::StoryPositionChanged(story_id, idx) => {
-                    self.change_story_position(story_id, idx);
-                }
-                GameAction::StoryRemoved(story_id) if is_admin => self.remove_story(story_id),
-                GameAction::VotingOpened(story_id) if is_admin => {
-                    self.open_story_for_voting(story_id);
-                }
-                GameAction::VotingClosed if is_admin => self.close_story_for_voting(),
-                GameAction::VotesRevealed if is_admin => self.reveal_votes(),
-                GameAction::VotesCleared if is_admin => self.clear_votes(),
-                GameAction::ResultsApproved(estimate) if is_admin => self.accept_round(estimate),
-                GameAction::VoteCasted(vote) => self.cast_vote(user_id, vote),
-                GameAction::PlayerLeft => self.remove_player(&user_id),
-                // we don't process the rest
-                GameAction::StoriesAdded(_)
-                | GameAction::StoryUpdated(_, _)
-                | GameAction::StoryRemoved(_)
-                | GameAction::PlayerJoined(_)
-                | GameAction::ResultsApproved(_)
-                | GameAction::VotingOpened(_)
-                | GameAction::VotingClosed
-                | GameAction::VotesCleared
-                | GameAction::VotesRevealed => (),
-            };
-        }
-    }
-
-    fn add_player(&mut self, user: User) {
+    /// Active players whose votes are expected, observers are left out.
+    pub fn to_active_voters(&self) -> IndexMap<UserId, Player> {
         self.players
-            .entry(user.id)
-            .or_insert_with(|| Player::new(user, PlayerRole::Player))
-            .active = true;
+            .iter()
+            .filter(|(_, player)| player.active && player.can_vote())
+            .map(|(user_id, player)| (*user_id, player.clone()))
+            .collect()
+    }
+
+    /// How many of the active voters have voted on the selected story, out of how many.
+    pub fn voting_progress(&self) -> Option<(usize, usize)> {
+        let story = self.selected_story.as_ref()?;
+        let voters = self.to_active_voters();
+        let voted = voters
+            .keys()
+            .filter(|user_id| story.votes.contains_key(*user_id))
+            .count();
+        Some((voted, voters.len()))
+    }
+
+    /// Applies the action of the user and returns what changed, nothing for no-op actions.
+    pub fn update(
+        &mut self,
+        user_id: UserId,
+        action: GameAction,
+    ) -> Result<Vec<GameEvent>, GameError> {
+        let mut events = self.apply_action(user_id, action)?;
+        if self.auto_reveal {
+            events.extend(self.reveal_when_everyone_voted());
+        }
+        if !events.is_empty() {
+            self.seq += 1;
+        }
+        Ok(events)
+    }
+
+    fn apply_action(
+        &mut self,
+        user_id: UserId,
+        action: GameAction,
+    ) -> Result<Vec<GameEvent>, GameError> {
+        if let GameAction::PlayerJoined(user) = action {
+            return Ok(self.add_player(user));
+        }
+        let player = self.players.get(&user_id).ok_or(GameError::NotAPlayer)?;
+        let is_admin = player.role == PlayerRole::Admin;
+        match action {
+            GameAction::StoriesAdded(stories) if is_admin => Ok(self.add_stories(stories)),
+            GameAction::StoryUpdated(story_id, story_info) if is_admin => {
+                self.update_story(story_id, story_info)
+            }
+            GameAction::StoryPositionChanged(story_id, idx) => {
+                self.change_story_position(story_id, idx)
+            }
+            GameAction::StoryRemoved(story_id) if is_admin => self.remove_story(story_id),
+            GameAction::VotingOpened(story_id, ended_at) if is_admin => {
+                self.open_story_for_voting(story_id, ended_at)
+            }
+            GameAction::VotingClosed(ended_at) if is_admin => self.close_story_for_voting(ended_at),
+            GameAction::VotesRevealed if is_admin => self.reveal_votes(),
+            GameAction::VotesCleared(ended_at) if is_admin => self.clear_votes(ended_at),
+            GameAction::ResultsApproved(estimate, ended_at) if is_admin => {
+                self.accept_round(estimate, ended_at)
+            }
+            GameAction::VoteCasted(vote) if player.can_vote() => self.cast_vote(user_id, vote),
+            GameAction::PlayerLeft => Ok(self.remove_player(user_id)),
+            GameAction::PlayerRoleChanged(player_id, role) if is_admin => {
+                self.change_role(player_id, role)
+            }
+            // players can switch between voting and watching on their own
+            GameAction::PlayerRoleChanged(player_id, role)
+                if player_id == user_id && role != PlayerRole::Admin =>
+            {
+                self.change_role(player_id, role)
+            }
+            GameAction::AdminTransferred(player_id) if is_admin => {
+                self.transfer_admin(user_id, player_id)
+            }
+            GameAction::AdminClaimed if player.active && !self.has_active_admin() => {
+                self.change_role(user_id, PlayerRole::Admin)
+            }
+            GameAction::AutoRevealChanged(auto_reveal) if is_admin => {
+                Ok(self.change_auto_reveal(auto_reveal))
+            }
+            GameAction::TimerStarted(timer) if is_admin => self.start_timer(timer),
+            GameAction::TimerStopped if is_admin => self.stop_timer(),
+            GameAction::TimerExpired(ends_at) => Ok(self.expire_timer(ends_at)),
+            GameAction::PresenceChanged(presence, at) => {
+                Ok(self.change_presence(user_id, presence, at))
+            }
+            GameAction::VoteCasted(_) => Err(GameError::CannotVote),
+            GameAction::AdminClaimed => Err(GameError::AdminActive),
+            // the rest is allowed only to admins
+            GameAction::StoriesAdded(_)
+            | GameAction::PlayerRoleChanged(_, _)
+            | GameAction::AdminTransferred(_)
+            | GameAction::AutoRevealChanged(_)
+            | GameAction::TimerStarted(_)
+            | GameAction::TimerStopped
+            | GameAction::StoryUpdated(_, _)
+            | GameAction::StoryRemoved(_)
+            | GameAction::PlayerJoined(_)
+            | GameAction::ResultsApproved(_, _)
+            | GameAction::VotingOpened(_, _)
+            | GameAction::VotingClosed(_)
+            | GameAction::VotesCleared(_)
+            | GameAction::VotesRevealed => Err(GameError::NotAdmin),
+        }
     }
 
-    fn remove_player(&mut self, user_id: &UserId) {
-        if let Some(player) = self.players.get_mut(user_id) {
-            player.active = false;
+    // a player resuming a connection that hasn't been dropped yet keeps playing as before
+    fn add_player(&mut self, user: User) -> Vec<GameEvent> {
+        let user_id = user.id;
+        match self.players.get_mut(&user_id) {
+            Some(player) if player.active && player.presence == Presence::Disconnected => {
+                player.presence = Presence::Online;
+                return vec![GameEvent::PresenceChanged(user_id, Presence::Online)];
+            }
+            Some(player) if player.active => return vec![],
+            Some(player) => {
+                player.active = true;
+                player.presence = Presence::Online;
+            }
+            None => {
+                self.players
+                    .insert(user_id, Player::new(user, PlayerRole::Player));
+            }
+        }
+        vec![GameEvent::PlayerJoined(user_id)]
+    }
+
+    fn remove_player(&mut self, user_id: UserId) -> Vec<GameEvent> {
+        match self.players.get_mut(&user_id) {
+            Some(player) if player.active => {
+                player.active = false;
+                player.presence = Presence::Disconnected;
+                vec![GameEvent::PlayerLeft(user_id)]
+            }
+            _ => vec![],
+        }
+    }
+
+    fn change_presence(
+        &mut self,
+        user_id: UserId,
+        presence: Presence,
+        at: Timestamp,
+    ) -> Vec<GameEvent> {
+        match self.players.get_mut(&user_id) {
+            Some(player) if player.presence != presence => {
+                player.presence = presence;
+                player.last_seen = Some(at);
+                vec![GameEvent::PresenceChanged(user_id, presence)]
+            }
+            _ => vec![],
         }
     }
 
-    fn add_stories(&mut self, stories: Vec<BacklogStory>) {
+    fn change_role(
+        &mut self,
+        player_id: UserId,
+        role: PlayerRole,
+    ) -> Result<Vec<GameEvent>, GameError> {
+        let admins_count = self
+            .players
+            .values()
+            .filter(|player| player.role == PlayerRole::Admin)
+            .count();
+        let player = self
+            .players
+            .get_mut(&player_id)
+            .ok_or(GameError::UnknownPlayer)?;
+        if player.role == role {
+            return Ok(vec![]);
+        }
+        // the game always needs at least one admin
+        if player.role == PlayerRole::Admin && admins_count == 1 {
+            return Err(GameError::LastAdmin);
+        }
+        player.role = role;
+        // votes of observers don't count
+        if role == PlayerRole::Observer {
+            if let Some(story) = self.selected_story.as_mut() {
+                story.votes.shift_remove(&player_id);
+            }
+        }
+        Ok(vec![GameEvent::RoleChanged(player_id, role)])
+    }
+
+    fn transfer_admin(
+        &mut self,
+        admin_id: UserId,
+        player_id: UserId,
+    ) -> Result<Vec<GameEvent>, GameError> {
+        if admin_id == player_id {
+            return Ok(vec![]);
+        }
+        let mut events = self.change_role(player_id, PlayerRole::Admin)?;
+        events.extend(self.change_role(admin_id, PlayerRole::Player)?);
+        Ok(events)
+    }
+
+    fn add_stories(&mut self, stories: Vec<BacklogStory>) -> Vec<GameEvent> {
+        if stories.is_empty() {
+            return vec![];
+        }
+        let story_ids = stories.iter().map(|story| story.id).collect();
         self.backlog_stories
             .extend(stories.into_iter().map(|s| (s.id, s)));
+        vec![GameEvent::StoriesAdded(story_ids)]
     }
 
-    fn change_story_position(&mut self, story_id: StoryId, new_idx: usize) {
+    fn change_story_position(
+        &mut self,
+        story_id: StoryId,
+        new_idx: usize,
+    ) -> Result<Vec<GameEvent>, GameError> {
         if new_idx >= self.backlog_stories.len() {
-            return;
+            return Err(GameError::IndexOutOfRange);
         }
-        if let Some((idx, _, _)) = self.backlog_stories.get_full(&story_id) {
-            self.backlog_stories.move_index(idx, new_idx);
+        let (idx, _, _) = self
+            .backlog_stories
+            .get_full(&story_id)
+            .ok_or(GameError::UnknownStory)?;
+        if idx == new_idx {
+            return Ok(vec![]);
         }
+        self.backlog_stories.move_index(idx, new_idx);
+        Ok(vec![GameEvent::StoryMoved(story_id, new_idx)])
     }
 
-    fn update_story(&mut self, story_id: StoryId, info: StoryInfo) {
-        if let Some(story) = self.backlog_stories.get_mut(&story_id) {
-            story.info = info;
-        }
+    fn update_story(
+        &mut self,
+        story_id: StoryId,
+        info: StoryInfo,
+    ) -> Result<Vec<GameEvent>, GameError> {
+        let story = self
+            .backlog_stories
+            .get_mut(&story_id)
+            .ok_or(GameError::UnknownStory)?;
+        story.info = info;
+        Ok(vec![GameEvent::StoryUpdated(story_id)])
     }
 
-    fn remove_story(&mut self, story_id: StoryId) {
-        self.backlog_stories.shift_remove(&story_id);
+    fn remove_story(&mut self, story_id: StoryId) -> Result<Vec<GameEvent>, GameError> {
+        self.backlog_stories
+            .shift_remove(&story_id)
+            .ok_or(GameError::UnknownStory)?;
+        Ok(vec![GameEvent::StoryRemoved(story_id)])
     }
 
-    fn open_story_for_voting(&mut self, story_id: StoryId) {
+    fn open_story_for_voting(
+        &mut self,
+        story_id: StoryId,
+        ended_at: Timestamp,
+    ) -> Result<Vec<GameEvent>, GameError> {
+        if !self.backlog_stories.contains_key(&story_id) {
+            return Err(GameError::UnknownStory);
+        }
         // if there was a story already open for voting add it back to backlog
-        self.close_story_for_voting();
+        let mut events = self.close_story_for_voting(ended_at).unwrap_or_default();
 
         if let Some(story) = self.backlog_stories.shift_remove(&story_id) {
             self.selected_story = story.select_for_estimation().into();
         }
+        events.push(GameEvent::VotingOpened(story_id));
+        Ok(events)
     }
 
-    fn close_story_for_voting(&mut self) {
-        if let Some(story) = self.selected_story.take() {
-            let story = story.into_backlog();
-            // TODO: add it at index 0
-            self.backlog_stories.insert(story.id, story);
+    fn close_story_for_voting(&mut self, ended_at: Timestamp) -> Result<Vec<GameEvent>, GameError> {
+        let story = self
+            .selected_story
+            .take()
+            .ok_or(GameError::NoStorySelected)?;
+        let story = story.into_backlog(ended_at);
+        let story_id = story.id;
+        // TODO: add it at index 0
+        self.backlog_stories.insert(story_id, story);
+        Ok(vec![GameEvent::VotingClosed(story_id)])
+    }
+
+    fn cast_vote(&mut self, player_id: UserId, vote: Vote) -> Result<Vec<GameEvent>, GameError> {
+        if !self.deck.contains(vote) {
+            return Err(GameError::InvalidVote);
+        }
+        let story = self
+            .selected_story
+            .as_mut()
+            .ok_or(GameError::NoStorySelected)?;
+        if story.votes_revealed {
+            return Err(GameError::VotesAlreadyRevealed);
         }
+        story.add_vote(player_id, vote);
+        Ok(vec![GameEvent::VoteCasted(player_id)])
     }
 
-    fn cast_vote(&mut self, player_id: UserId, vote: Vote) {
-        if let Some(ref mut story) = self.selected_story {
-            if !story.votes_revealed {
-                story.add_vote(player_id, vote);
-            }
+    fn reveal_votes(&mut self) -> Result<Vec<GameEvent>, GameError> {
+        let story = self
+            .selected_story
+            .as_mut()
+            .ok_or(GameError::NoStorySelected)?;
+        if story.votes_revealed {
+            return Err(GameError::VotesAlreadyRevealed);
+        }
+        if story.votes.is_empty() {
+            return Err(GameError::NoVotes);
         }
+        story.reveal_votes();
+        Ok(vec![GameEvent::VotesRevealed(story.id)])
     }
 
-    fn reveal_votes(&mut self) {
-        if let Some(story) = self
+    // leaving voters or new observers can complete the voting as well, not only a casted vote
+    fn reveal_when_everyone_voted(&mut self) -> Vec<GameEvent> {
+        if matches!(self.voting_progress(), Some((voted, voters)) if voted > 0 && voted == voters) {
+            self.reveal_votes().unwrap_or_default()
+        } else {
+            vec![]
+        }
+    }
+
+    fn change_auto_reveal(&mut self, auto_reveal: bool) -> Vec<GameEvent> {
+        if self.auto_reveal == auto_reveal {
+            return vec![];
+        }
+        self.auto_reveal = auto_reveal;
+        vec![GameEvent::AutoRevealChanged(auto_reveal)]
+    }
+
+    fn start_timer(&mut self, timer: RoundTimer) -> Result<Vec<GameEvent>, GameError> {
+        let story = self
             .selected_story
             .as_mut()
-            .filter(|story| !story.votes_revealed && !story.votes.is_empty())
-        {
-            story.reveal_votes();
+            .ok_or(GameError::NoStorySelected)?;
+        if story.votes_revealed {
+            return Err(GameError::VotesAlreadyRevealed);
         }
+        story.timer = Some(timer);
+        Ok(vec![GameEvent::TimerStarted(timer)])
     }
 
-    fn clear_votes(&mut self) {
-        if let Some(story) = self.selected_story.as_mut() {
-            story.clear_votes();
+    fn stop_timer(&mut self) -> Result<Vec<GameEvent>, GameError> {
+        let story = self
+            .selected_story
+            .as_mut()
+            .ok_or(GameError::NoStorySelected)?;
+        Ok(story
+            .timer
+            .take()
+            .map(|_| GameEvent::TimerStopped)
+            .into_iter()
+            .collect())
+    }
+
+    // expiring a timer that was restarted or stopped in the meantime is a valid no-op
+    fn expire_timer(&mut self, ends_at: Timestamp) -> Vec<GameEvent> {
+        let Some(story) = self.selected_story.as_mut() else {
+            return vec![];
+        };
+        let Some(timer) = story.timer.filter(|timer| timer.ends_at == ends_at) else {
+            return vec![];
+        };
+        story.timer = None;
+        let mut events = vec![GameEvent::TimerExpired];
+        if timer.auto_reveal {
+            events.extend(self.reveal_votes().unwrap_or_default());
         }
+        events
     }
 
-    fn accept_round(&mut self, estimate: Option<Vote>) {
-        if self.selected_story.is_none()
-            || matches!(
-                &self.selected_story,
-                Some(story) if !story.votes_revealed || story.votes.is_empty()
-            )
-        {
-            return;
+    fn clear_votes(&mut self, ended_at: Timestamp) -> Result<Vec<GameEvent>, GameError> {
+        let story = self
+            .selected_story
+            .as_mut()
+            .ok_or(GameError::NoStorySelected)?;
+        story.clear_votes(ended_at);
+        Ok(vec![GameEvent::VotesCleared(story.id)])
+    }
+
+    fn accept_round(
+        &mut self,
+        estimate: Option<Vote>,
+        ended_at: Timestamp,
+    ) -> Result<Vec<GameEvent>, GameError> {
+        if matches!(estimate, Some(vote) if !self.deck.contains(vote)) {
+            return Err(GameError::InvalidVote);
+        }
+        let story = self
+            .selected_story
+            .as_ref()
+            .ok_or(GameError::NoStorySelected)?;
+        if !story.votes_revealed {
+            return Err(GameError::VotesNotRevealed);
+        }
+        if story.votes.is_empty() {
+            return Err(GameError::NoVotes);
         }
 
+        // without an explicit estimate we need at least one numeric vote to compute it
+        let estimate = estimate
+            .or_else(|| {
+                let avrg = story.votes_avrg()?;
+                Some(Vote::get_closest_vote(&avrg, &self.deck))
+            })
+            .ok_or(GameError::NoEstimate)?;
+
         let story =
             self.selected_story.take().unwrap(/* checked above that some value is contained */);
-        let estimate = estimate.unwrap_or_else(|| {
-            let avrg = story.votes_avrg();
-            Vote::get_closest_vote(&avrg)
+        let story = story.accept_with_estimate(estimate, ended_at);
+        let story_id = story.id;
+        self.estimated_stories.insert(story_id, story);
+        Ok(vec![GameEvent::StoryEstimated(story_id, estimate)])
+    }
+}
+
+/// Milliseconds since the unix epoch.
+pub type Timestamp = u64;
+
+/// Action that was applied to a game.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub struct LoggedAction {
+    pub user_id: UserId,
+    pub action: GameAction,
+    pub timestamp: Timestamp,
+}
+
+/// Countdown of a voting round.
+#[derive(PartialEq, Eq, Clone, Copy, Serialize, Deserialize, JsonSchema, Debug)]
+pub struct RoundTimer {
+    pub started_at: Timestamp,
+    pub ends_at: Timestamp,
+    /// Reveal the votes when the time is up.
+    pub auto_reveal: bool,
+}
+
+impl RoundTimer {
+    /// Longest countdown the server starts.
+    pub const MAX_DURATION_MS: u64 = 60 * 60 * 1000;
+
+    pub fn new(started_at: Timestamp, duration_ms: u64, auto_reveal: bool) -> Self {
+        RoundTimer {
+            started_at,
+            ends_at: started_at.saturating_add(duration_ms),
+            auto_reveal,
+        }
+    }
+
+    pub fn duration_ms(&self) -> u64 {
+        self.ends_at.saturating_sub(self.started_at)
+    }
+
+    /// Milliseconds left at the given time.
+    pub fn remaining_ms(&self, now: Timestamp) -> u64 {
+        self.ends_at.saturating_sub(now).min(self.duration_ms())
+    }
+}
+
+/// History of a game from its creation.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub struct GameLog {
+    pub game_id: GameId,
+    pub creator: User,
+    #[serde(default)]
+    pub deck: Deck,
+    pub actions: Vec<LoggedAction>,
+}
+
+impl GameLog {
+    pub fn new(game_id: GameId, creator: User, deck: Deck) -> Self {
+        GameLog {
+            game_id,
+            creator,
+            deck,
+            actions: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, user_id: UserId, action: GameAction, timestamp: Timestamp) {
+        self.actions.push(LoggedAction {
+            user_id,
+            action,
+            timestamp,
         });
-        let story = story.accept_with_estimate(estimate);
-        self.estimated_stories.insert(story.id, story);
+    }
+
+    /// Rebuilds the game by applying every logged action to a freshly created game.
+    pub fn replay(&self) -> Game {
+        self.actions.iter().fold(
+            Game::with_id(self.game_id, self.creator.clone(), self.deck),
+            |mut game, logged| {
+                // only applied actions are logged, so they apply again
+                let replayed = game.update(logged.user_id, logged.action.clone());
+                debug_assert!(
+                    matches!(&replayed, Ok(events) if !events.is_empty()),
+                    "logged action {:?} doesn't apply again: {replayed:?}",
+                    logged.action
+                );
+                game
+            },
+        )
     }
 }
 
-#[derive(PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize, Debug, derive_more::Display)]
+#[derive(
+    PartialEq,
+    Eq,
+    Hash,
+    Clone,
+    Copy,
+    Serialize,
+    Deserialize,
+    JsonSchema,
+    Debug,
+    derive_more::Display,
+)]
 pub struct StoryId(Uuid);
 
-#[derive(PartialEq, Eq, Clone, Serialize, Deserialize, Debug)]
+#[derive(PartialEq, Eq, Clone, Default, Serialize, Deserialize, JsonSchema, Debug)]
 pub struct StoryInfo {
     pub title: String,
+    /// Key of the story in an external tracker, e.g. `PROJ-123` or `#42`.
+    #[serde(default)]
+    pub key: Option<String>,
+    /// Details of the story and its acceptance criteria, in markdown.
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Link to the story in an external tracker.
+    #[serde(default)]
+    pub url: Option<String>,
+    #[serde(default)]
+    pub labels: Vec<String>,
+    #[serde(default)]
+    pub assignee: Option<String>,
+}
+
+impl StoryInfo {
+    pub fn new(title: String) -> Self {
+        StoryInfo {
+            title,
+            ..StoryInfo::default()
+        }
+    }
 }
 
 /// Story that is waiting in the backlog to be selected for estimation.
-#[derive(PartialEq, Eq, Clone, Serialize, Deserialize, Debug)]
+#[derive(PartialEq, Eq, Clone, Serialize, Deserialize, JsonSchema, Debug)]
 pub struct BacklogStory {
     pub id: StoryId,
     pub info: StoryInfo,
+    /// The story was selected for estimation and put back without an estimate.
+    #[serde(default)]
+    pub skipped: bool,
+    /// Rounds played before the story was put back.
+    #[serde(default)]
+    pub rounds: Vec<VotingRound>,
 }
 
 impl BacklogStory {
@@ -250,6 +857,8 @@ impl BacklogStory {
         BacklogStory {
             id: StoryId(Uuid::new_v4()),
             info,
+            skipped: false,
+            rounds: Vec::new(),
         }
     }
 
@@ -259,17 +868,24 @@ impl BacklogStory {
             info: self.info.clone(),
             votes: IndexMap::new(),
             votes_revealed: false,
+            timer: None,
+            rounds: self.rounds.clone(),
         }
     }
 }
 
 /// Story that is selected for estimation.
-#[derive(PartialEq, Eq, Clone, Serialize, Deserialize, Debug)]
+#[derive(PartialEq, Eq, Clone, Serialize, Deserialize, JsonSchema, Debug)]
 pub struct SelectedStory {
     pub id: StoryId,
     pub info: StoryInfo,
     pub votes: IndexMap<UserId, Vote>,
     pub votes_revealed: bool,
+    #[serde(default)]
+    pub timer: Option<RoundTimer>,
+    /// Rounds played before the current one.
+    #[serde(default)]
+    pub rounds: Vec<VotingRound>,
 }
 
 impl SelectedStory {
@@ -291,77 +907,142 @@ impl SelectedStory {
 
     pub fn reveal_votes(&mut self) {
         self.votes_revealed = true;
+        self.timer = None;
     }
 
-    pub fn clear_votes(&mut self) {
+    pub fn clear_votes(&mut self, ended_at: Timestamp) {
+        self.end_round(ended_at);
         self.votes_revealed = false;
-        self.votes.clear();
+        self.timer = None;
     }
 
-    pub fn accept_with_estimate(&self, estimate: Vote) -> EstimatedStory {
+    pub fn accept_with_estimate(mut self, estimate: Vote, ended_at: Timestamp) -> EstimatedStory {
+        self.end_round(ended_at);
         EstimatedStory {
             id: self.id,
-            info: self.info.clone(),
+            info: self.info,
             estimate,
+            rounds: self.rounds,
         }
     }
 
-    pub fn into_backlog(self) -> BacklogStory {
+    // keep the votes of the current round, rounds nobody voted in aren't worth keeping
+    fn end_round(&mut self, ended_at: Timestamp) {
+        if self.votes.is_empty() {
+            return;
+        }
+        self.rounds.push(VotingRound {
+            votes: std::mem::take(&mut self.votes),
+            revealed: self.votes_revealed,
+            ended_at,
+        });
+    }
+
+    /// Puts the story back, the votes of the current round are kept with the previous ones.
+    pub fn into_backlog(mut self, ended_at: Timestamp) -> BacklogStory {
+        self.end_round(ended_at);
         BacklogStory {
             id: self.id,
             info: self.info,
+            skipped: true,
+            rounds: self.rounds,
         }
     }
 
-    pub fn votes_avrg(&self) -> f32 {
-        if self.votes.is_empty() {
-            0.
-        } else {
-            let val = self.votes.iter().map(|(_, vote)| vote.0).sum::<i32>();
-            #[allow(clippy::cast_precision_loss)]
-            (val as f32 / self.votes.len() as f32)
-        }
+    /// Average of the numeric votes, special cards are not taken into account.
+    pub fn votes_avrg(&self) -> Option<f32> {
+        votes_avrg(&self.votes)
+    }
+
+    pub fn count_votes(&self, vote: Vote) -> usize {
+        self.votes
+            .values()
+            .filter(|casted| **casted == vote)
+            .count()
+    }
+}
+
+/// Votes of a finished round of a story.
+#[derive(PartialEq, Eq, Clone, Serialize, Deserialize, JsonSchema, Debug)]
+pub struct VotingRound {
+    pub votes: IndexMap<UserId, Vote>,
+    pub revealed: bool,
+    pub ended_at: Timestamp,
+}
+
+impl VotingRound {
+    /// Average of the numeric votes, special cards are not taken into account.
+    pub fn votes_avrg(&self) -> Option<f32> {
+        votes_avrg(&self.votes)
+    }
+}
+
+fn votes_avrg(votes: &IndexMap<UserId, Vote>) -> Option<f32> {
+    let values = votes.values().filter_map(Vote::value).collect::<Vec<_>>();
+    if values.is_empty() {
+        None
+    } else {
+        let val = values.iter().sum::<i32>();
+        #[allow(clippy::cast_precision_loss)]
+        Some(val as f32 / values.len() as f32)
     }
 }
 
 /// Story that is estimated.
-#[derive(PartialEq, Eq, Clone, Serialize, Deserialize, Debug)]
+#[derive(PartialEq, Eq, Clone, Serialize, Deserialize, JsonSchema, Debug)]
 pub struct EstimatedStory {
     pub id: StoryId,
     pub info: StoryInfo,
     pub estimate: Vote,
+    /// Every round played until the estimate was accepted, the last one included.
+    #[serde(default)]
+    pub rounds: Vec<VotingRound>,
 }
 
-#[derive(PartialEq, Eq, Clone, Copy, Serialize, Deserialize, Debug)]
-pub struct Vote(i32);
-
-const VOTES: [i32; 10] = [0, 1, 2, 3, 5, 8, 13, 21, 40, 100];
+#[derive(PartialEq, Eq, Clone, Copy, Serialize, Deserialize, JsonSchema, Debug)]
+pub enum Vote {
+    Points(i32),
+    /// The player doesn't know how to estimate the story.
+    Unsure,
+    /// The player needs a break.
+    Break,
+    /// The story is too big to be estimated.
+    TooBig,
+}
 
 impl Vote {
-    pub fn new(value: i32) -> Result<Vote, String> {
-        if VOTES.contains(&value) {
-            Ok(Self(value))
+    /// Cards available in every deck next to the numeric ones.
+    pub const SPECIAL: [Vote; 3] = [Vote::Unsure, Vote::Break, Vote::TooBig];
+
+    pub fn new(value: i32, deck: &Deck) -> Result<Vote, String> {
+        if deck.values().contains(&value) {
+            Ok(Vote::Points(value))
         } else {
             Err("Not allowed value".to_string())
         }
     }
 
-    pub fn value(&self) -> i32 {
-        self.0
-    }
-
-    pub fn get_allowed_values() -> [i32; 10] {
-        VOTES
+    /// Numeric value of the vote, `None` for special cards.
+    pub fn value(&self) -> Option<i32> {
+        match self {
+            Vote::Points(value) => Some(*value),
+            Vote::Unsure | Vote::Break | Vote::TooBig => None,
+        }
     }
 
-    #[allow(clippy::missing_panics_doc)]
-    pub fn get_allowed_votes() -> [Vote; 10] {
-        VOTES.map(|val| Vote::new(val).unwrap(/* safe because we iterate over VOTES */))
+    pub fn get_allowed_votes(deck: &Deck) -> Vec<Vote> {
+        deck.values()
+            .iter()
+            .copied()
+            .map(Vote::Points)
+            .chain(Vote::SPECIAL)
+            .collect()
     }
 
     #[allow(clippy::missing_panics_doc)]
-    pub fn get_closest_vote(value: &f32) -> Vote {
-        let closest = VOTES
+    pub fn get_closest_vote(value: &f32, deck: &Deck) -> Vote {
+        let closest = deck
+            .values()
             .iter()
             .reduce(|prev, curr| {
                 #[allow(clippy::cast_precision_loss)]
@@ -371,15 +1052,100 @@ impl Vote {
                     prev
                 }
             })
-            .unwrap(/* safe because we iterate over VOTES */);
-        Vote(*closest)
+            .unwrap(/* safe because no deck is empty */);
+        Vote::Points(*closest)
     }
 }
 
-#[derive(PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize, Debug, derive_more::Display)]
+/// Cards that players can vote with in a game.
+#[derive(PartialEq, Eq, Clone, Copy, Default, Serialize, Deserialize, JsonSchema, Debug)]
+pub enum Deck {
+    #[default]
+    ModifiedFibonacci,
+    Fibonacci,
+    PowersOfTwo,
+    TShirtSizes,
+    Hours,
+}
+
+const T_SHIRT_SIZES: [(i32, &str); 6] = [
+    (1, "XS"),
+    (2, "S"),
+    (3, "M"),
+    (5, "L"),
+    (8, "XL"),
+    (13, "XXL"),
+];
+
+impl Deck {
+    pub const ALL: [Deck; 5] = [
+        Deck::ModifiedFibonacci,
+        Deck::Fibonacci,
+        Deck::PowersOfTwo,
+        Deck::TShirtSizes,
+        Deck::Hours,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Deck::ModifiedFibonacci => "Modified Fibonacci",
+            Deck::Fibonacci => "Fibonacci",
+            Deck::PowersOfTwo => "Powers of 2",
+            Deck::TShirtSizes => "T-shirt sizes",
+            Deck::Hours => "Hours",
+        }
+    }
+
+    /// Numeric values of the cards, used to compute averages.
+    pub fn values(&self) -> &'static [i32] {
+        match self {
+            Deck::ModifiedFibonacci => &[0, 1, 2, 3, 5, 8, 13, 21, 40, 100],
+            Deck::Fibonacci => &[0, 1, 2, 3, 5, 8, 13, 21, 34, 55, 89],
+            Deck::PowersOfTwo => &[0, 1, 2, 4, 8, 16, 32, 64],
+            Deck::TShirtSizes => &[1, 2, 3, 5, 8, 13],
+            Deck::Hours => &[1, 2, 4, 8, 16, 24, 40],
+        }
+    }
+
+    pub fn contains(&self, vote: Vote) -> bool {
+        vote.value()
+            .map_or(true, |value| self.values().contains(&value))
+    }
+
+    /// Text shown on the card.
+    pub fn label(&self, vote: Vote) -> String {
+        let value = match vote {
+            Vote::Points(value) => value,
+            Vote::Unsure => return "?".to_string(),
+            Vote::Break => return "☕".to_string(),
+            Vote::TooBig => return "∞".to_string(),
+        };
+        match self {
+            Deck::TShirtSizes => T_SHIRT_SIZES
+                .iter()
+                .find(|(size_value, _)| *size_value == value)
+                .map_or_else(|| value.to_string(), |(_, size)| (*size).to_string()),
+            Deck::Hours => format!("{value}h"),
+            Deck::ModifiedFibonacci | Deck::Fibonacci | Deck::PowersOfTwo => value.to_string(),
+        }
+    }
+}
+
+#[derive(
+    PartialEq,
+    Eq,
+    Hash,
+    Clone,
+    Copy,
+    Serialize,
+    Deserialize,
+    JsonSchema,
+    Debug,
+    derive_more::Display,
+)]
 pub struct UserId(Uuid);
 
-#[derive(PartialEq, Eq, Clone, Serialize, Deserialize, Debug)]
+#[derive(PartialEq, Eq, Clone, Serialize, Deserialize, JsonSchema, Debug)]
 pub struct User {
     pub id: UserId,
     // TODO: move this maybe to Player struct to allow for different names in different games/teams
@@ -395,26 +1161,324 @@ impl User {
     }
 }
 
-#[derive(PartialEq, Eq, Clone, Copy, Serialize, Deserialize, Debug)]
+#[derive(PartialEq, Eq, Clone, Copy, Serialize, Deserialize, JsonSchema, Debug)]
 pub enum PlayerRole {
     Admin,
     Player,
+    /// Watches the game without voting.
+    Observer,
+}
+
+/// Whether the player is around, reported by the client and by the server on disconnection.
+#[derive(PartialEq, Eq, Clone, Copy, Default, Serialize, Deserialize, JsonSchema, Debug)]
+pub enum Presence {
+    #[default]
+    Online,
+    /// Hasn't touched the game for a while.
+    Idle,
+    /// Has the game in a hidden tab.
+    Away,
+    /// Lost the connection, but can still come back.
+    Disconnected,
 }
 
-#[derive(PartialEq, Eq, Clone, Serialize, Deserialize, Debug)]
+#[derive(PartialEq, Eq, Clone, Serialize, Deserialize, JsonSchema, Debug)]
 pub struct Player {
     pub user: User,
     pub role: PlayerRole,
     pub active: bool,
+    #[serde(default)]
+    pub presence: Presence,
+    /// When the presence last changed, so when an absent player was seen for the last time.
+    #[serde(default)]
+    pub last_seen: Option<Timestamp>,
 }
 
 impl Player {
     pub fn new(user: User, role: PlayerRole) -> Self {
         let active = true;
-        Player { user, role, active }
+        Player {
+            user,
+            role,
+            active,
+            presence: Presence::Online,
+            last_seen: None,
+        }
     }
 
     pub fn new_admin(user: User) -> Self {
         Player::new(user, PlayerRole::Admin)
     }
+
+    pub fn can_vote(&self) -> bool {
+        self.role != PlayerRole::Observer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Table {
+        game: Game,
+        admin: UserId,
+        player: UserId,
+        observer: UserId,
+        story: StoryId,
+    }
+
+    // an admin, a player and an observer with one story in the backlog
+    fn table() -> Table {
+        let admin = User::new("Alice".to_string());
+        let player = User::new("Bob".to_string());
+        let observer = User::new("Carol".to_string());
+        let story = BacklogStory::new(StoryInfo::new("Login".to_string()));
+        let (admin_id, player_id, observer_id, story_id) =
+            (admin.id, player.id, observer.id, story.id);
+
+        let mut game = Game::new(admin, Deck::default());
+        game.update(player_id, GameAction::PlayerJoined(player))
+            .unwrap();
+        game.update(observer_id, GameAction::PlayerJoined(observer))
+            .unwrap();
+        game.update(
+            observer_id,
+            GameAction::PlayerRoleChanged(observer_id, PlayerRole::Observer),
+        )
+        .unwrap();
+        game.update(admin_id, GameAction::StoriesAdded(vec![story]))
+            .unwrap();
+        Table {
+            game,
+            admin: admin_id,
+            player: player_id,
+            observer: observer_id,
+            story: story_id,
+        }
+    }
+
+    #[test]
+    fn strangers_cant_play() {
+        let Table { mut game, .. } = table();
+        let stranger = User::new("Mallory".to_string()).id;
+        assert_eq!(
+            game.update(stranger, GameAction::VotesRevealed),
+            Err(GameError::NotAPlayer)
+        );
+    }
+
+    #[test]
+    fn only_admins_manage_the_game() {
+        let Table {
+            mut game,
+            player,
+            story,
+            ..
+        } = table();
+        assert_eq!(
+            game.update(player, GameAction::VotingOpened(story, 0)),
+            Err(GameError::NotAdmin)
+        );
+        assert_eq!(
+            game.update(player, GameAction::StoryRemoved(story)),
+            Err(GameError::NotAdmin)
+        );
+    }
+
+    #[test]
+    fn observers_cant_vote() {
+        let Table {
+            mut game,
+            admin,
+            observer,
+            story,
+            ..
+        } = table();
+        game.update(admin, GameAction::VotingOpened(story, 0))
+            .unwrap();
+        assert_eq!(
+            game.update(observer, GameAction::VoteCasted(Vote::Points(3))),
+            Err(GameError::CannotVote)
+        );
+    }
+
+    #[test]
+    fn the_last_admin_stays() {
+        let Table {
+            mut game, admin, ..
+        } = table();
+        assert_eq!(
+            game.update(
+                admin,
+                GameAction::PlayerRoleChanged(admin, PlayerRole::Player)
+            ),
+            Err(GameError::LastAdmin)
+        );
+    }
+
+    #[test]
+    fn stories_have_to_be_in_the_backlog() {
+        let Table {
+            mut game,
+            admin,
+            story,
+            ..
+        } = table();
+        let unknown = BacklogStory::new(StoryInfo::new("Logout".to_string())).id;
+        assert_eq!(
+            game.update(admin, GameAction::VotingOpened(unknown, 0)),
+            Err(GameError::UnknownStory)
+        );
+        assert_eq!(
+            game.update(admin, GameAction::StoryPositionChanged(unknown, 0)),
+            Err(GameError::UnknownStory)
+        );
+        assert_eq!(
+            game.update(admin, GameAction::StoryPositionChanged(story, 1)),
+            Err(GameError::IndexOutOfRange)
+        );
+    }
+
+    #[test]
+    fn rounds_need_a_selected_story() {
+        let Table {
+            mut game, admin, ..
+        } = table();
+        assert_eq!(
+            game.update(admin, GameAction::VotesRevealed),
+            Err(GameError::NoStorySelected)
+        );
+        assert_eq!(
+            game.update(admin, GameAction::VoteCasted(Vote::Points(3))),
+            Err(GameError::NoStorySelected)
+        );
+        assert_eq!(
+            game.update(admin, GameAction::ResultsApproved(None, 0)),
+            Err(GameError::NoStorySelected)
+        );
+    }
+
+    #[test]
+    fn votes_are_revealed_before_accepting_and_only_once() {
+        let Table {
+            mut game,
+            admin,
+            player,
+            story,
+            ..
+        } = table();
+        game.update(admin, GameAction::VotingOpened(story, 0))
+            .unwrap();
+        game.update(player, GameAction::VoteCasted(Vote::Points(3)))
+            .unwrap();
+        assert_eq!(
+            game.update(admin, GameAction::ResultsApproved(None, 0)),
+            Err(GameError::VotesNotRevealed)
+        );
+        game.update(admin, GameAction::VotesRevealed).unwrap();
+        assert_eq!(
+            game.update(admin, GameAction::VotesRevealed),
+            Err(GameError::VotesAlreadyRevealed)
+        );
+        assert_eq!(
+            game.update(player, GameAction::VoteCasted(Vote::Points(5))),
+            Err(GameError::VotesAlreadyRevealed)
+        );
+    }
+
+    #[test]
+    fn special_cards_dont_give_an_estimate() {
+        let Table {
+            mut game,
+            admin,
+            player,
+            story,
+            ..
+        } = table();
+        game.update(admin, GameAction::VotingOpened(story, 0))
+            .unwrap();
+        game.update(player, GameAction::VoteCasted(Vote::Unsure))
+            .unwrap();
+        game.update(admin, GameAction::VotesRevealed).unwrap();
+        assert_eq!(
+            game.update(admin, GameAction::ResultsApproved(None, 0)),
+            Err(GameError::NoEstimate)
+        );
+        assert_eq!(
+            game.update(admin, GameAction::ResultsApproved(Some(Vote::Points(5)), 0)),
+            Ok(vec![GameEvent::StoryEstimated(story, Vote::Points(5))])
+        );
+    }
+
+    #[test]
+    fn put_back_stories_keep_their_rounds() {
+        let Table {
+            mut game,
+            admin,
+            player,
+            story,
+            ..
+        } = table();
+        let other = BacklogStory::new(StoryInfo::new("Logout".to_string()));
+        let other_id = other.id;
+        let actions = [
+            (admin, GameAction::StoriesAdded(vec![other])),
+            (admin, GameAction::VotingOpened(story, 1)),
+            (player, GameAction::VoteCasted(Vote::Points(3))),
+            (admin, GameAction::VotingClosed(2)),
+            (admin, GameAction::VotingOpened(story, 3)),
+            (player, GameAction::VoteCasted(Vote::Points(5))),
+            // the round of the story ends when another story is selected
+            (admin, GameAction::VotingOpened(other_id, 4)),
+            (admin, GameAction::VotingOpened(story, 5)),
+            (player, GameAction::VoteCasted(Vote::Points(8))),
+            (admin, GameAction::VotesRevealed),
+            (admin, GameAction::ResultsApproved(None, 6)),
+        ];
+        for (user_id, action) in actions {
+            game.update(user_id, action).unwrap();
+        }
+        let rounds = &game.estimated_stories[&story].rounds;
+        let votes = rounds
+            .iter()
+            .map(|round| (round.votes[&player], round.ended_at))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            votes,
+            [
+                (Vote::Points(3), 2),
+                (Vote::Points(5), 4),
+                (Vote::Points(8), 6)
+            ]
+        );
+    }
+
+    #[test]
+    fn rejected_and_noop_actions_keep_the_seq() {
+        let Table {
+            mut game,
+            admin,
+            player,
+            story,
+            ..
+        } = table();
+        let seq = game.seq;
+        assert!(game
+            .update(player, GameAction::VotingOpened(story, 0))
+            .is_err());
+        assert_eq!(
+            game.update(admin, GameAction::AutoRevealChanged(false)),
+            Ok(vec![])
+        );
+        assert_eq!(
+            game.update(admin, GameAction::StoryPositionChanged(story, 0)),
+            Ok(vec![])
+        );
+        assert_eq!(game.update(admin, GameAction::TimerExpired(0)), Ok(vec![]));
+        assert_eq!(game.seq, seq);
+
+        game.update(admin, GameAction::AutoRevealChanged(true))
+            .unwrap();
+        assert_eq!(game.seq, seq + 1);
+    }
 }
@@ -0,0 +1,39 @@
+//! Checks of the links of stories, which come from other players or imported issues.
+
+/// Whether the link can't run scripts, only web and mail links or ones without a scheme are.
+pub fn is_safe_url(url: &str) -> bool {
+    let lowercase = url.trim().to_lowercase();
+    let has_scheme = lowercase
+        .split_once(':')
+        .map_or(false, |(scheme, _)| !scheme.contains('/'));
+    let is_allowed = ["http:", "https:", "mailto:"]
+        .iter()
+        .any(|scheme| lowercase.starts_with(scheme));
+    !has_scheme || is_allowed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_links_that_cant_run_scripts_are_safe() {
+        for url in [
+            "https://example.com/a?b=c",
+            "HTTP://example.com",
+            "mailto:a@example.com",
+            "/browse/POK-1",
+            "page/a:b",
+        ] {
+            assert!(is_safe_url(url), "{url}");
+        }
+        for url in [
+            "javascript:alert(1)",
+            " JavaScript:alert(1)",
+            "data:text/html,<p>",
+            "vbscript:x",
+        ] {
+            assert!(!is_safe_url(url), "{url}");
+        }
+    }
+}
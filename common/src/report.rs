@@ -0,0 +1,335 @@
+//! Summary of a refinement session, for sharing the results after the meeting.
+
+use crate::{
+    links::is_safe_url, EstimatedStory, Game, PlayerRole, StoryInfo, UserId, Vote, VotingRound,
+};
+use indexmap::IndexMap;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+
+#[derive(PartialEq, Clone, Serialize, Deserialize, JsonSchema, Debug)]
+pub struct GameSummary {
+    pub deck: String,
+    /// Sum of the numeric estimates.
+    pub total_points: i32,
+    pub participants: Vec<Participant>,
+    pub estimated_stories: Vec<StorySummary>,
+    /// Stories that were put back into the backlog without an estimate.
+    pub skipped_stories: Vec<StoryRef>,
+    /// Stories still waiting in the backlog, the skipped ones included.
+    pub remaining_stories: usize,
+}
+
+#[derive(PartialEq, Eq, Clone, Serialize, Deserialize, JsonSchema, Debug)]
+pub struct Participant {
+    pub name: String,
+    pub role: PlayerRole,
+}
+
+#[derive(PartialEq, Eq, Clone, Serialize, Deserialize, JsonSchema, Debug)]
+pub struct StoryRef {
+    pub key: Option<String>,
+    pub title: String,
+    pub url: Option<String>,
+}
+
+impl From<&StoryInfo> for StoryRef {
+    fn from(info: &StoryInfo) -> Self {
+        StoryRef {
+            key: info.key.clone(),
+            title: info.title.clone(),
+            url: info.url.clone(),
+        }
+    }
+}
+
+#[derive(PartialEq, Clone, Serialize, Deserialize, JsonSchema, Debug)]
+pub struct StorySummary {
+    pub story: StoryRef,
+    pub estimate: String,
+    pub points: Option<i32>,
+    pub rounds: usize,
+    pub spread: VoteSpread,
+}
+
+/// Votes of the round the estimate was accepted in.
+#[derive(PartialEq, Clone, Serialize, Deserialize, JsonSchema, Debug)]
+pub struct VoteSpread {
+    pub min: Option<i32>,
+    pub max: Option<i32>,
+    pub average: Option<f32>,
+    /// Player name and the label of the card they voted with.
+    pub votes: Vec<(String, String)>,
+}
+
+impl GameSummary {
+    pub fn new(game: &Game) -> Self {
+        let estimated_stories = game
+            .estimated_stories
+            .values()
+            .map(|story| story_summary(game, story))
+            .collect::<Vec<_>>();
+        GameSummary {
+            deck: game.deck.name().to_string(),
+            total_points: estimated_stories
+                .iter()
+                .filter_map(|story| story.points)
+                .sum(),
+            participants: game
+                .players
+                .values()
+                .map(|player| Participant {
+                    name: player.user.name.clone(),
+                    role: player.role,
+                })
+                .collect(),
+            estimated_stories,
+            skipped_stories: game
+                .backlog_stories
+                .values()
+                .filter(|story| story.skipped)
+                .map(|story| StoryRef::from(&story.info))
+                .collect(),
+            remaining_stories: game.backlog_stories.len(),
+        }
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut md = String::from("# Planning poker summary\n\n");
+        // writing into a string never fails
+        let _ = writeln!(md, "- Deck: {}", self.deck);
+        let _ = writeln!(md, "- Total points: {}", self.total_points);
+        let _ = writeln!(md, "- Estimated stories: {}", self.estimated_stories.len());
+        let _ = writeln!(
+            md,
+            "- Stories left in the backlog: {}",
+            self.remaining_stories
+        );
+        let participants = self
+            .participants
+            .iter()
+            .map(|participant| match participant.role {
+                PlayerRole::Admin => format!("{} (moderator)", participant.name),
+                PlayerRole::Observer => format!("{} (observer)", participant.name),
+                PlayerRole::Player => participant.name.clone(),
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        let _ = writeln!(md, "- Participants: {participants}");
+
+        if !self.estimated_stories.is_empty() {
+            md.push_str("\n## Estimated stories\n\n");
+            md.push_str("| Story | Estimate | Rounds | Spread | Votes |\n");
+            md.push_str("| --- | --- | --- | --- | --- |\n");
+            for summary in &self.estimated_stories {
+                let spread = match (summary.spread.min, summary.spread.max) {
+                    (Some(min), Some(max)) => format!("{min}–{max}"),
+                    _ => "-".to_string(),
+                };
+                let votes = summary
+                    .spread
+                    .votes
+                    .iter()
+                    .map(|(name, vote)| format!("{name}: {vote}"))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                let _ = writeln!(
+                    md,
+                    "| {} | {} | {} | {} | {} |",
+                    story_link(&summary.story),
+                    table_cell(&summary.estimate),
+                    summary.rounds,
+                    spread,
+                    table_cell(&votes),
+                );
+            }
+        }
+
+        if !self.skipped_stories.is_empty() {
+            md.push_str("\n## Skipped stories\n\n");
+            for story in &self.skipped_stories {
+                let _ = writeln!(md, "- {}", story_link(story));
+            }
+        }
+        md
+    }
+}
+
+fn story_summary(game: &Game, story: &EstimatedStory) -> StorySummary {
+    let player_name = |user_id: &UserId| {
+        game.players
+            .get(user_id)
+            .map_or_else(|| "unknown".to_string(), |player| player.user.name.clone())
+    };
+    let final_round = story.rounds.last();
+    let votes = final_round.map(|round| &round.votes);
+    let values = votes
+        .into_iter()
+        .flat_map(IndexMap::values)
+        .filter_map(Vote::value);
+    StorySummary {
+        story: StoryRef::from(&story.info),
+        estimate: game.deck.label(story.estimate),
+        points: story.estimate.value(),
+        rounds: story.rounds.len(),
+        spread: VoteSpread {
+            min: values.clone().min(),
+            max: values.max(),
+            average: final_round.and_then(VotingRound::votes_avrg),
+            votes: votes
+                .into_iter()
+                .flatten()
+                .map(|(user_id, vote)| (player_name(user_id), game.deck.label(*vote)))
+                .collect(),
+        },
+    }
+}
+
+fn story_link(story: &StoryRef) -> String {
+    let title = match &story.key {
+        Some(key) => format!("{key} {}", story.title),
+        None => story.title.clone(),
+    };
+    match &story.url {
+        Some(url) if is_safe_url(url) => format!("[{}]({})", table_cell(&title), link_target(url)),
+        _ => table_cell(&title),
+    }
+}
+
+// in angle brackets the target can have spaces and parentheses, but not the brackets themselves
+fn link_target(url: &str) -> String {
+    let url = url
+        .trim()
+        .replace('<', "%3C")
+        .replace('>', "%3E")
+        .replace(['\n', '\r'], "");
+    format!("<{}>", table_cell(&url))
+}
+
+// pipes and line breaks would break the table
+fn table_cell(text: &str) -> String {
+    text.replace('|', "\\|").replace('\n', " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BacklogStory, Deck, GameAction, User};
+
+    // one story estimated in two rounds, one with a special card, one skipped and one left
+    fn played_game() -> Game {
+        let admin = User::new("Alice".to_string());
+        let player = User::new("Bob".to_string());
+        let (admin_id, player_id) = (admin.id, player.id);
+        let login = BacklogStory::new(StoryInfo {
+            key: Some("POK-1".to_string()),
+            url: Some("https://example.com/POK-1".to_string()),
+            ..StoryInfo::new("Login | SSO".to_string())
+        });
+        let logout = BacklogStory::new(StoryInfo {
+            url: Some("javascript:alert(1)".to_string()),
+            ..StoryInfo::new("Logout\nand forget".to_string())
+        });
+        let reset = BacklogStory::new(StoryInfo {
+            url: Some("https://example.com/wiki/Reset (password)".to_string()),
+            ..StoryInfo::new("Reset the password".to_string())
+        });
+        let signup = BacklogStory::new(StoryInfo::new("Sign up".to_string()));
+        let ids = [login.id, logout.id, reset.id];
+
+        let mut game = Game::new(admin, Deck::default());
+        let actions = [
+            (player_id, GameAction::PlayerJoined(player)),
+            (
+                admin_id,
+                GameAction::StoriesAdded(vec![login, logout, reset, signup]),
+            ),
+            (admin_id, GameAction::VotingOpened(ids[0], 0)),
+            (admin_id, GameAction::VoteCasted(Vote::Points(3))),
+            (player_id, GameAction::VoteCasted(Vote::Points(13))),
+            (admin_id, GameAction::VotesRevealed),
+            (admin_id, GameAction::VotesCleared(1)),
+            (admin_id, GameAction::VoteCasted(Vote::Points(5))),
+            (player_id, GameAction::VoteCasted(Vote::Points(8))),
+            (admin_id, GameAction::VotesRevealed),
+            (
+                admin_id,
+                GameAction::ResultsApproved(Some(Vote::Points(8)), 2),
+            ),
+            (admin_id, GameAction::VotingOpened(ids[1], 0)),
+            (admin_id, GameAction::VoteCasted(Vote::Unsure)),
+            (player_id, GameAction::VoteCasted(Vote::TooBig)),
+            (admin_id, GameAction::VotesRevealed),
+            (admin_id, GameAction::ResultsApproved(Some(Vote::TooBig), 3)),
+            (admin_id, GameAction::VotingOpened(ids[2], 0)),
+            (admin_id, GameAction::VotingClosed(0)),
+        ];
+        for (user_id, action) in actions {
+            game.update(user_id, action).unwrap();
+        }
+        game
+    }
+
+    #[test]
+    fn special_cards_dont_add_points() {
+        let summary = GameSummary::new(&played_game());
+        assert_eq!(summary.total_points, 8);
+        let estimates = summary
+            .estimated_stories
+            .iter()
+            .map(|story| (story.estimate.as_str(), story.points))
+            .collect::<Vec<_>>();
+        assert_eq!(estimates, [("8", Some(8)), ("∞", None)]);
+        assert_eq!(summary.remaining_stories, 2);
+        assert_eq!(
+            summary.skipped_stories,
+            [StoryRef {
+                key: None,
+                title: "Reset the password".to_string(),
+                url: Some("https://example.com/wiki/Reset (password)".to_string()),
+            }]
+        );
+    }
+
+    #[test]
+    fn the_spread_comes_from_the_last_round() {
+        let summary = GameSummary::new(&played_game());
+        let login = &summary.estimated_stories[0];
+        assert_eq!(login.rounds, 2);
+        assert_eq!(
+            login.spread,
+            VoteSpread {
+                min: Some(5),
+                max: Some(8),
+                average: Some(6.5),
+                votes: vec![
+                    ("Alice".to_string(), "5".to_string()),
+                    ("Bob".to_string(), "8".to_string()),
+                ],
+            }
+        );
+        let logout = &summary.estimated_stories[1];
+        assert_eq!((logout.spread.min, logout.spread.max), (None, None));
+        assert_eq!(logout.spread.average, None);
+    }
+
+    #[test]
+    fn table_cells_are_escaped() {
+        let markdown = GameSummary::new(&played_game()).to_markdown();
+        assert!(markdown.contains(
+            "| [POK-1 Login \\| SSO](<https://example.com/POK-1>) | 8 | 2 | 5–8 | Alice: 5, Bob: 8 |\n"
+        ));
+        assert!(markdown.contains("- Participants: Alice (moderator), Bob\n"));
+    }
+
+    #[test]
+    fn only_safe_links_are_kept() {
+        let markdown = GameSummary::new(&played_game()).to_markdown();
+        assert!(!markdown.contains("javascript:"));
+        assert!(markdown.contains("| Logout and forget | ∞ | 1 | - | Alice: ?, Bob: ∞ |\n"));
+        assert!(markdown.contains(
+            "## Skipped stories\n\n- [Reset the password](<https://example.com/wiki/Reset (password)>)\n"
+        ));
+    }
+}
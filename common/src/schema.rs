@@ -0,0 +1,49 @@
+//! JSON Schemas of the types exchanged with the server, for clients written in other languages.
+
+use schemars::{
+    gen::{SchemaGenerator, SchemaSettings},
+    schema::{RootSchema, Schema},
+    Map,
+};
+
+use crate::{
+    issue_import::ImportFormat,
+    report::GameSummary,
+    webhook::{NewWebhook, Webhook, WebhookDelivery, WebhookPayload},
+    AppEvent, BacklogStory, Game, GameAction, GameCreated, GameEvent, GameLog, NewGame, StoryInfo,
+    VersionInfo,
+};
+
+/// Schema of the websocket messages, with the definitions of every other type of the wire format.
+pub fn wire_schema() -> RootSchema {
+    let mut gen = SchemaSettings::draft07().into_generator();
+    add_definitions(&mut gen);
+    gen.into_root_schema_for::<AppEvent>()
+}
+
+/// Definitions of the same types, referenced as `#/components/schemas/<name>` for an `OpenAPI` 3 document.
+pub fn openapi_schemas() -> Map<String, Schema> {
+    let mut gen = SchemaSettings::openapi3().into_generator();
+    add_definitions(&mut gen);
+    gen.take_definitions()
+}
+
+// the types sent over the websocket and the HTTP routes
+fn add_definitions(gen: &mut SchemaGenerator) {
+    gen.subschema_for::<AppEvent>();
+    gen.subschema_for::<GameAction>();
+    gen.subschema_for::<GameEvent>();
+    gen.subschema_for::<NewGame>();
+    gen.subschema_for::<GameCreated>();
+    gen.subschema_for::<Game>();
+    gen.subschema_for::<GameLog>();
+    gen.subschema_for::<GameSummary>();
+    gen.subschema_for::<StoryInfo>();
+    gen.subschema_for::<BacklogStory>();
+    gen.subschema_for::<ImportFormat>();
+    gen.subschema_for::<VersionInfo>();
+    gen.subschema_for::<NewWebhook>();
+    gen.subschema_for::<Webhook>();
+    gen.subschema_for::<WebhookDelivery>();
+    gen.subschema_for::<WebhookPayload>();
+}
@@ -0,0 +1,154 @@
+//! Notifications the server posts to other services when something happens in a game.
+
+use crate::{report::GameSummary, BacklogStory, EstimatedStory, GameId, SelectedStory, Timestamp};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Hash,
+    Serialize,
+    Deserialize,
+    JsonSchema,
+    Debug,
+    derive_more::Display,
+)]
+pub struct WebhookId(Uuid);
+
+impl WebhookId {
+    pub fn random() -> Self {
+        Self(Uuid::new_v4())
+    }
+}
+
+#[derive(
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Hash,
+    Serialize,
+    Deserialize,
+    JsonSchema,
+    Debug,
+    derive_more::Display,
+)]
+pub struct DeliveryId(Uuid);
+
+impl DeliveryId {
+    pub fn random() -> Self {
+        Self(Uuid::new_v4())
+    }
+}
+
+/// Kinds of events a webhook can subscribe to.
+#[derive(
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Hash,
+    Serialize,
+    Deserialize,
+    JsonSchema,
+    Debug,
+    derive_more::Display,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEvent {
+    #[display(fmt = "stories_added")]
+    StoriesAdded,
+    #[display(fmt = "voting_opened")]
+    VotingOpened,
+    #[display(fmt = "results_approved")]
+    ResultsApproved,
+    /// The last story of the backlog got estimated.
+    #[display(fmt = "game_finished")]
+    GameFinished,
+}
+
+/// Body of the webhook registration request.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema, Debug)]
+pub struct NewWebhook {
+    pub url: String,
+    /// Events to post, every one of them when empty.
+    #[serde(default)]
+    pub events: Vec<WebhookEvent>,
+    /// Key of the HMAC-SHA256 signature sent in the `X-Webhook-Signature` header.
+    pub secret: String,
+}
+
+/// Registered webhook, its secret is never sent back.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema, Debug)]
+pub struct Webhook {
+    pub id: WebhookId,
+    pub url: String,
+    pub events: Vec<WebhookEvent>,
+}
+
+impl Webhook {
+    pub fn wants(&self, event: WebhookEvent) -> bool {
+        self.events.is_empty() || self.events.contains(&event)
+    }
+}
+
+/// What happened, with the state of the game it concerns.
+#[derive(Clone, PartialEq, Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(tag = "event", content = "data", rename_all = "snake_case")]
+pub enum WebhookData {
+    StoriesAdded(Vec<BacklogStory>),
+    VotingOpened(SelectedStory),
+    ResultsApproved(EstimatedStory),
+    GameFinished(GameSummary),
+}
+
+impl WebhookData {
+    pub fn event(&self) -> WebhookEvent {
+        match self {
+            WebhookData::StoriesAdded(_) => WebhookEvent::StoriesAdded,
+            WebhookData::VotingOpened(_) => WebhookEvent::VotingOpened,
+            WebhookData::ResultsApproved(_) => WebhookEvent::ResultsApproved,
+            WebhookData::GameFinished(_) => WebhookEvent::GameFinished,
+        }
+    }
+}
+
+/// Body posted to the webhook, every attempt of a delivery sends the same one.
+#[derive(Clone, PartialEq, Serialize, Deserialize, JsonSchema, Debug)]
+pub struct WebhookPayload {
+    pub delivery_id: DeliveryId,
+    pub game_id: GameId,
+    pub timestamp: Timestamp,
+    #[serde(flatten)]
+    pub data: WebhookData,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryStatus {
+    /// Waiting for the next attempt.
+    Pending,
+    Delivered,
+    /// Every attempt failed.
+    Failed,
+}
+
+/// Entry of the delivery log of a game.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema, Debug)]
+pub struct WebhookDelivery {
+    pub id: DeliveryId,
+    pub webhook_id: WebhookId,
+    pub event: WebhookEvent,
+    pub status: DeliveryStatus,
+    pub attempts: u32,
+    /// HTTP status of the last response.
+    pub response_status: Option<u16>,
+    /// Why the last attempt failed.
+    pub error: Option<String>,
+    pub created_at: Timestamp,
+    pub updated_at: Timestamp,
+}
@@ -0,0 +1,16 @@
+use std::{env, fs, path::Path};
+
+// other clients are generated from the committed schema, so it has to follow the Rust types
+#[test]
+fn wire_schema_is_up_to_date() {
+    let schema = serde_json::to_string_pretty(&common::schema::wire_schema()).unwrap() + "\n";
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("schema.json");
+    if env::var_os("UPDATE_SCHEMA").is_some() {
+        fs::write(&path, &schema).unwrap();
+    }
+    let committed = fs::read_to_string(&path).unwrap_or_default();
+    assert!(
+        committed == schema,
+        "the wire format changed, run `UPDATE_SCHEMA=1 cargo test -p common` and commit schema.json"
+    );
+}
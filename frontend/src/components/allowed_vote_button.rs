@@ -1,9 +1,8 @@
-use common::Vote;
 use yew::prelude::*;
 
 #[derive(Clone, PartialEq, Properties)]
 pub(crate) struct Props {
-    pub(crate) vote: Vote,
+    pub(crate) label: String,
     #[prop_or_else(Callback::noop)]
     pub(crate) onclick: Callback<MouseEvent>,
 }
@@ -22,7 +21,7 @@ pub(crate) fn allowed_vote_button(props: &Props) -> Html {
             )}
             {onclick}
         >
-            { props.vote.value() }
+            { &props.label }
         </button>
     }
 }
@@ -1,16 +1,17 @@
-use common::Vote;
+use common::{Deck, Vote};
 use yew::prelude::*;
 
 use crate::components::allowed_vote_button::AllowedVoteButton;
 
 #[derive(Clone, PartialEq, Properties)]
 pub(crate) struct Props {
+    pub(crate) deck: Deck,
     pub(crate) on_vote_click: Callback<Vote>,
 }
 
 #[function_component(AllowedVotes)]
 pub(crate) fn allowed_votes(props: &Props) -> Html {
-    let allowed_votes = Vote::get_allowed_votes()
+    let allowed_votes = Vote::get_allowed_votes(&props.deck)
         .iter()
         .map(|vote| {
             let onclick = {
@@ -18,9 +19,10 @@ pub(crate) fn allowed_votes(props: &Props) -> Html {
                 let on_vote_click = props.on_vote_click.clone();
                 Callback::from(move |_| on_vote_click.emit(vote))
             };
+            let label = props.deck.label(*vote);
             html! {
-                <div key={vote.value()} class="m-1">
-                    <AllowedVoteButton vote={*vote} {onclick} />
+                <div key={label.clone()} class="m-1">
+                    <AllowedVoteButton {label} {onclick} />
                 </div>
             }
         })
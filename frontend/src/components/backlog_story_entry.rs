@@ -1,10 +1,10 @@
 use common::{BacklogStory, GameAction, StoryInfo};
-use web_sys::HtmlInputElement;
 use yew::prelude::*;
 
 use crate::components::{
-    form_input::FormInput,
     icons::{CancelIcon, EditIcon, GoDownIcon, GoUpIcon, RemoveIcon, SelectIcon},
+    story_details::StoryDetails,
+    story_info_form::StoryInfoForm,
 };
 
 #[derive(Clone, Debug, PartialEq, Properties)]
@@ -23,11 +23,13 @@ enum EntryState {
 #[function_component(BacklogStoryEntry)]
 pub(crate) fn backlog_story_entry(props: &Props) -> Html {
     let state = use_state(|| EntryState::Default);
+    let expanded = use_state(|| false);
 
     let on_select = {
         let story_id = props.story.id;
         let on_action = props.on_action.clone();
-        Callback::from(move |_| on_action.emit(GameAction::VotingOpened(story_id)))
+        // the server sets when the round of the selected story ended
+        Callback::from(move |_| on_action.emit(GameAction::VotingOpened(story_id, 0)))
     };
     let on_remove = {
         let story_id = props.story.id;
@@ -58,24 +60,19 @@ pub(crate) fn backlog_story_entry(props: &Props) -> Html {
         let on_action = props.on_action.clone();
         Callback::from(move |_| on_action.emit(GameAction::StoryPositionChanged(story_id, new_idx)))
     };
-    let onkeypress = {
+    let on_submit = {
         let state = state.clone();
         let story_id = props.story.id;
         let on_action = props.on_action.clone();
-        Callback::from(move |e: KeyboardEvent| {
-            if e.key() == "Enter" {
-                let input: HtmlInputElement = e.target_unchecked_into();
-                let value = input.value();
-                let title = value.trim();
-
-                if !title.is_empty() {
-                    let title = title.to_string();
-                    on_action.emit(GameAction::StoryUpdated(story_id, StoryInfo { title }));
-                    state.set(EntryState::Default);
-                }
-            }
+        Callback::from(move |info: StoryInfo| {
+            on_action.emit(GameAction::StoryUpdated(story_id, info));
+            state.set(EntryState::Default);
         })
     };
+    let on_toggle_details = {
+        let expanded = expanded.clone();
+        Callback::from(move |_| expanded.set(!*expanded))
+    };
 
     let button_class = "m-1 p-1";
     #[allow(clippy::let_unit_value)]
@@ -158,14 +155,14 @@ pub(crate) fn backlog_story_entry(props: &Props) -> Html {
     html! {
         <li class="py-4 px-4 border-b flex items-center hover:bg-slate-100 text-slate-500">
             if let EntryState::Editing = *state {
-                <FormInput
-                    value={props.story.info.title.clone()}
-                    {onkeypress}
-                />
+                <StoryInfoForm info={props.story.info.clone()} {on_submit} />
             } else {
-                <h4 class="flex-1 text-base">
-                    {props.story.info.title.clone()}
-                </h4>
+                <div class="flex-1">
+                    <h4 class="text-base cursor-pointer" onclick={on_toggle_details}>
+                        {props.story.info.title.clone()}
+                    </h4>
+                    <StoryDetails info={props.story.info.clone()} show_description={*expanded} />
+                </div>
             }
             <div class="ml-8 flex">
                 {buttons}
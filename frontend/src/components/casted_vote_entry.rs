@@ -1,4 +1,4 @@
-use common::{Player, Vote};
+use common::{Deck, Player, Vote};
 
 use yew::prelude::*;
 
@@ -7,6 +7,7 @@ pub(crate) struct Props {
     pub(crate) vote: Option<Vote>,
     pub(crate) player: Player,
     pub(crate) is_revealed: bool,
+    pub(crate) deck: Deck,
 }
 
 #[function_component(CastedVoteEntry)]
@@ -27,9 +28,9 @@ pub(crate) fn casted_vote_entry(props: &Props) -> Html {
                     props.is_revealed.then_some("bg-slate-50"),
                 )}
             >
-                if props.is_revealed && props.vote.is_some() {
+                if let (true, Some(vote)) = (props.is_revealed, props.vote) {
                     <strong class="block">
-                        { props.vote.unwrap().value() }
+                        { props.deck.label(vote) }
                     </strong>
                 }
             </div>
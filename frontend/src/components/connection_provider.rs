@@ -1,11 +1,18 @@
-use common::{AppEvent, GameAction, GameId, User};
+use common::{
+    AppEvent, Deck, ErrorCode, GameAction, GameCreated, GameId, NewGame, SessionToken, User,
+    PROTOCOL_VERSION,
+};
 use gloo_net::http::Request;
+use gloo_storage::{LocalStorage, Storage};
+use gloo_timers::callback::Timeout;
 use yew::prelude::*;
 use yew_hooks::{
     use_async, use_location, use_websocket_with_options, UseAsyncHandle, UseWebSocketOptions,
     UseWebSocketReadyState,
 };
 
+use crate::components::round_countdown;
+
 // use dotenv_codegen::dotenv;
 // const API_BASE_URL: &str = dotenv!("API_BASE_URL");
 
@@ -14,6 +21,12 @@ pub(crate) struct Connection {
     pub(crate) ready_state: UseWebSocketReadyState,
     pub(crate) message: Option<String>,
     pub(crate) send: Callback<GameAction>,
+    /// asks the server for the current state of the game
+    pub(crate) resync: Callback<()>,
+    /// last error reported by the server
+    pub(crate) error: Option<(ErrorCode, String)>,
+    /// milliseconds the clock of the server is ahead of the browser one
+    pub(crate) clock_offset: i64,
 }
 
 #[hook]
@@ -29,37 +42,68 @@ pub(crate) fn use_game_connection(game_id: &GameId, user: &User) -> Connection {
     let ws = use_websocket_with_options(
         ws_url,
         UseWebSocketOptions {
-            reconnect_limit: Some(1000),
+            // reconnecting is done here, with a growing delay
+            reconnect_limit: Some(0),
             ..Default::default()
         },
     );
 
-    let send_msg = {
+    let send_event = {
         let ws = ws.clone();
-        let user_id = user.id;
-        move |action: GameAction| {
-            let msg = AppEvent::GameMessage(user_id, action);
-            let msg = serde_json::to_string(&msg).unwrap();
+        move |event: &AppEvent| {
+            let msg = serde_json::to_string(event).unwrap();
             ws.send(msg);
         }
     };
 
+    let send_msg = {
+        let send_event = send_event.clone();
+        let user_id = user.id;
+        move |action: GameAction| send_event(&AppEvent::GameMessage(user_id, action))
+    };
+
+    let resync = {
+        let send_event = send_event.clone();
+        move |()| send_event(&AppEvent::ResyncRequested)
+    };
+
+    let reconnect_attempts = use_mut_ref(|| 0);
+    let reconnect_timeout = use_mut_ref(|| None::<Timeout>);
+    // there's nothing to reconnect to once the server doesn't know the game
+    let game_gone = use_mut_ref(|| false);
     {
+        let reconnect_timeout = reconnect_timeout.clone();
+        let game_gone = game_gone.clone();
         let ws_state = ws.ready_state.clone();
-        let send_msg = send_msg.clone();
+        let ws = ws.clone();
+        let game_id = *game_id;
         let user = user.clone();
-        // Send `join` message when the connection opens
+        // Send `hello` and `join` messages when the connection opens, joining again with
+        // the stored token resumes the session
         use_effect_with_deps(
             move |ws_state| {
                 match **ws_state {
                     UseWebSocketReadyState::Open => {
-                        send_msg(GameAction::PlayerJoined(user));
+                        *reconnect_attempts.borrow_mut() = 0;
+                        send_event(&AppEvent::Hello(PROTOCOL_VERSION));
+                        let token = load_session_token(&game_id);
+                        send_event(&AppEvent::JoinGame(user, token));
+                    }
+                    UseWebSocketReadyState::Closed if *game_gone.borrow() => {
+                        log::info!("game {} is gone, not reconnecting", game_id);
                     }
                     UseWebSocketReadyState::Closed => {
-                        // wait some time and reconnect
-                        // ws.open();
+                        let attempt = reconnect_attempts.replace_with(|attempt| *attempt + 1);
+                        let delay = reconnect_delay(attempt);
+                        log::info!("connection closed, reconnecting in {} ms", delay);
+                        *reconnect_timeout.borrow_mut() =
+                            Some(Timeout::new(delay, move || ws.open()));
                     }
-                    UseWebSocketReadyState::Closing | UseWebSocketReadyState::Connecting => (),
+                    // the socket starts closed, so a reconnect could be pending already
+                    UseWebSocketReadyState::Connecting => {
+                        reconnect_timeout.borrow_mut().take();
+                    }
+                    UseWebSocketReadyState::Closing => (),
                 }
                 || ()
             },
@@ -67,25 +111,76 @@ pub(crate) fn use_game_connection(game_id: &GameId, user: &User) -> Connection {
         );
     }
 
+    let error = use_state(|| None);
+    let clock_offset = use_state(|| 0_i64);
+    {
+        let game_id = *game_id;
+        let error = error.clone();
+        let clock_offset = clock_offset.clone();
+        // Keep the token issued by the server to be able to join again later
+        use_effect_with_deps(
+            move |message| {
+                if let Some(message) = &**message {
+                    match serde_json::from_str(message) {
+                        Ok(AppEvent::SessionStarted(token)) => {
+                            store_session_token(&game_id, &token);
+                        }
+                        Ok(AppEvent::GameNotFound(_)) => {
+                            *game_gone.borrow_mut() = true;
+                            reconnect_timeout.borrow_mut().take();
+                        }
+                        Ok(AppEvent::SessionRejected) => {
+                            log::error!("session for game {} was rejected", game_id);
+                        }
+                        Ok(AppEvent::Hello(version)) if version != PROTOCOL_VERSION => {
+                            log::error!("server speaks protocol version {}", version);
+                        }
+                        Ok(AppEvent::ServerTime(server_now)) => {
+                            clock_offset.set(round_countdown::clock_offset(server_now));
+                        }
+                        Ok(AppEvent::Error { code, message, .. }) => {
+                            log::error!("server rejected a message: {}", message);
+                            error.set(Some((code, message)));
+                        }
+                        _ => (),
+                    }
+                }
+                || ()
+            },
+            ws.message.clone(),
+        );
+    }
+
     Connection {
         ready_state: (*ws.ready_state).clone(),
         message: (*ws.message).clone(),
         send: Callback::from(send_msg),
+        resync: Callback::from(resync),
+        error: (*error).clone(),
+        clock_offset: *clock_offset,
     }
 }
 
 #[hook]
-pub(crate) fn use_crate_game_req(user: &User) -> UseAsyncHandle<GameId, Error> {
-    let user = user.clone();
-    use_async(async move { create_game_req(&user).await })
+pub(crate) fn use_crate_game_req(user: &User, deck: Deck) -> UseAsyncHandle<GameId, Error> {
+    let new_game = NewGame {
+        user: user.clone(),
+        deck,
+    };
+    use_async(async move { create_game_req(&new_game).await })
 }
 
-async fn create_game_req(user: &User) -> Result<GameId, Error> {
-    let response = Request::post("/api/game").json(user).unwrap().send().await;
+async fn create_game_req(new_game: &NewGame) -> Result<GameId, Error> {
+    let response = Request::post("/api/game")
+        .json(new_game)
+        .unwrap()
+        .send()
+        .await;
 
     if let Ok(data) = response {
-        if let Ok(game_id) = data.json::<GameId>().await {
-            Ok(game_id)
+        if let Ok(GameCreated { id, token }) = data.json::<GameCreated>().await {
+            store_session_token(&id, &token);
+            Ok(id)
         } else {
             Err(Error::DeserializeError)
         }
@@ -94,6 +189,25 @@ async fn create_game_req(user: &User) -> Result<GameId, Error> {
     }
 }
 
+// doubles with every failed attempt, up to half a minute
+fn reconnect_delay(attempt: u32) -> u32 {
+    500_u32
+        .saturating_mul(2_u32.saturating_pow(attempt))
+        .min(30_000)
+}
+
+fn session_storage_key(game_id: &GameId) -> String {
+    format!("yew.session.{game_id}")
+}
+
+fn load_session_token(game_id: &GameId) -> Option<SessionToken> {
+    LocalStorage::get(session_storage_key(game_id)).ok()
+}
+
+fn store_session_token(game_id: &GameId, token: &SessionToken) {
+    LocalStorage::set(session_storage_key(game_id), token).expect("failed to set");
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub(crate) enum Error {
     RequestError,
@@ -1,4 +1,4 @@
-use common::{EstimatedStory, StoryId};
+use common::{Deck, EstimatedStory, Player, StoryId, UserId};
 use indexmap::IndexMap;
 use yew::prelude::*;
 
@@ -7,6 +7,8 @@ use crate::components::estimated_story_entry::EstimatedStoryEntry;
 #[derive(Clone, Debug, PartialEq, Eq, Properties)]
 pub(crate) struct Props {
     pub(crate) stories: IndexMap<StoryId, EstimatedStory>,
+    pub(crate) players: IndexMap<UserId, Player>,
+    pub(crate) deck: Deck,
 }
 
 #[function_component(EstimatedStories)]
@@ -19,6 +21,8 @@ pub(crate) fn estimated_stories(props: &Props) -> Html {
                 <EstimatedStoryEntry
                     key={id.to_string()}
                     story={story.clone()}
+                    players={props.players.clone()}
+                    deck={props.deck}
                 />
             }
         })
@@ -1,34 +1,103 @@
-use common::EstimatedStory;
+use common::{Deck, EstimatedStory, Player, UserId, VotingRound};
+use indexmap::IndexMap;
 use yew::prelude::*;
 
 #[derive(Clone, Debug, PartialEq, Eq, Properties)]
 pub(crate) struct Props {
     pub(crate) story: EstimatedStory,
+    pub(crate) players: IndexMap<UserId, Player>,
+    pub(crate) deck: Deck,
 }
 
 #[function_component(EstimatedStoryEntry)]
 pub(crate) fn estimated_story_entry(props: &Props) -> Html {
-    let estimation = &props.story.estimate.value();
+    let expanded = use_state(|| false);
+    let estimation = props.deck.label(props.story.estimate);
+
+    let onclick = {
+        let expanded = expanded.clone();
+        Callback::from(move |_| expanded.set(!*expanded))
+    };
+
+    let rounds = props
+        .story
+        .rounds
+        .iter()
+        .enumerate()
+        .map(|(idx, round)| round_entry(idx, round, &props.players, props.deck))
+        .collect::<Html>();
 
     html! {
-        <li class="flex items-center px-2 border-b">
-            <h4
-                class={classes!(
-                    "flex-auto", "p-2",
-                    "font-bold", "text-xs", "text-slate-500"
-                )}
-            >
-                {&props.story.info.title}
-            </h4>
-            <strong
-                class={classes!(
-                    "m-2", "py-1", "px-2",
-                    "font-bold", "text-xs", "text-green-700",
-                    "rounded", "bg-green-200"
+        <li class="px-2 border-b">
+            <div class="flex items-center cursor-pointer" {onclick}>
+                <h4
+                    class={classes!(
+                        "flex-auto", "p-2",
+                        "font-bold", "text-xs", "text-slate-500"
+                    )}
+                >
+                    {&props.story.info.title}
+                </h4>
+                if !props.story.rounds.is_empty() {
+                    <span class="m-2 text-xs text-slate-400">
+                        {format!("{} round(s)", props.story.rounds.len())}
+                    </span>
+                }
+                <strong
+                    class={classes!(
+                        "m-2", "py-1", "px-2",
+                        "font-bold", "text-xs", "text-green-700",
+                        "rounded", "bg-green-200"
+                    )}
+                >
+                    {estimation}
+                </strong>
+            </div>
+            if *expanded {
+                <ol class="pb-2 px-2 list-none text-xs text-slate-500">
+                    {rounds}
+                </ol>
+            }
+        </li>
+    }
+}
+
+fn round_entry(
+    idx: usize,
+    round: &VotingRound,
+    players: &IndexMap<UserId, Player>,
+    deck: Deck,
+) -> Html {
+    let votes = round
+        .votes
+        .iter()
+        .map(|(user_id, vote)| {
+            let name = players
+                .get(user_id)
+                .map_or("unknown", |player| player.user.name.as_str());
+            format!("{name}: {}", deck.label(*vote))
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    let avrg = round
+        .votes_avrg()
+        .map_or_else(|| "-".to_string(), |avrg| avrg.to_string());
+    #[allow(clippy::cast_precision_loss)]
+    let ended_at = js_sys::Date::new(&(round.ended_at as f64).into());
+
+    html! {
+        <li key={idx} class="py-1">
+            <b>{format!("Round {} ", idx + 1)}</b>
+            <span class="text-slate-400">
+                {format!(
+                    "({:02}:{:02}{}) ",
+                    ended_at.get_hours(),
+                    ended_at.get_minutes(),
+                    if round.revealed { "" } else { ", not revealed" },
                 )}
-            >
-                {estimation}
-            </strong>
+            </span>
+            {votes}
+            {format!(" — average: {avrg}")}
         </li>
     }
 }
@@ -10,6 +10,8 @@ pub(crate) struct FormInputProps {
     pub(crate) value: String,
     #[prop_or_else(Callback::noop)]
     pub(crate) onkeypress: Callback<KeyboardEvent>,
+    #[prop_or_else(Callback::noop)]
+    pub(crate) oninput: Callback<InputEvent>,
 }
 
 #[function_component(FormInput)]
@@ -42,6 +44,7 @@ pub(crate) fn form_input(props: &FormInputProps) -> Html {
                 value={props.value.clone()}
                 placeholder={props.placeholder.clone()}
                 onkeypress={&props.onkeypress}
+                oninput={&props.oninput}
             />
         </div>
     }
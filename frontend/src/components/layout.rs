@@ -1,3 +1,4 @@
+use crate::components::update_notice::UpdateNotice;
 use yew::prelude::*;
 use yew_hooks::use_location;
 
@@ -15,6 +16,7 @@ pub(crate) fn layout(props: &LayoutProps) -> Html {
     html! {
         <div class="min-h-screen bg-slate-200 flex flex-col">
             <main class="p-4 flex-1" style="flex-basis: 0;">
+                <UpdateNotice />
                 { props.children.clone() }
             </main>
             <footer class="p-6 flex justify-end">
@@ -0,0 +1,48 @@
+use common::links::is_safe_url;
+use pulldown_cmark::{html, CowStr, Event, Options, Parser, Tag};
+use yew::prelude::*;
+
+#[derive(Clone, Debug, PartialEq, Eq, Properties)]
+pub(crate) struct Props {
+    pub(crate) source: String,
+}
+
+#[function_component(Markdown)]
+pub(crate) fn markdown(props: &Props) -> Html {
+    let rendered = use_memo(|source| render(source), props.source.clone());
+
+    html! {
+        <div class="markdown text-sm text-slate-600">
+            {Html::from_html_unchecked(AttrValue::from((*rendered).clone()))}
+        </div>
+    }
+}
+
+// raw html is shown as text and only safe link targets are kept, so the output can't run scripts
+fn render(source: &str) -> String {
+    let parser = Parser::new_ext(
+        source,
+        Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH,
+    )
+    .map(|event| match event {
+        Event::Html(raw) => Event::Text(raw),
+        Event::Start(Tag::Link(kind, url, title)) => {
+            Event::Start(Tag::Link(kind, safe_url(url), title))
+        }
+        Event::Start(Tag::Image(kind, url, title)) => {
+            Event::Start(Tag::Image(kind, safe_url(url), title))
+        }
+        event => event,
+    });
+    let mut output = String::new();
+    html::push_html(&mut output, parser);
+    output
+}
+
+fn safe_url(url: CowStr<'_>) -> CowStr<'_> {
+    if is_safe_url(&url) {
+        url
+    } else {
+        CowStr::Borrowed("#")
+    }
+}
@@ -13,8 +13,14 @@ pub(crate) mod form_textarea;
 pub(crate) mod icons;
 pub(crate) mod layout;
 pub(crate) mod login;
+pub(crate) mod markdown;
 pub(crate) mod player_entry;
 pub(crate) mod players;
+pub(crate) mod presence_tracker;
+pub(crate) mod round_countdown;
 pub(crate) mod selected_story_entry;
+pub(crate) mod story_details;
 pub(crate) mod story_form;
+pub(crate) mod story_info_form;
+pub(crate) mod update_notice;
 pub(crate) mod user_provider;
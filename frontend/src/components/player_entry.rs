@@ -1,28 +1,134 @@
-use common::{Player, PlayerRole, User};
+use common::{GameAction, Player, PlayerRole, Presence, Timestamp, User};
 use yew::prelude::*;
+use yew_hooks::use_interval;
 
-#[derive(Clone, PartialEq, Eq, Properties)]
+use crate::components::round_countdown::browser_now;
+
+#[derive(Clone, PartialEq, Properties)]
 pub(crate) struct Props {
     pub(crate) player: Player,
+    pub(crate) is_admin: bool,
+    pub(crate) on_action: Callback<GameAction>,
 }
 
 #[function_component(PlayerEntry)]
 pub(crate) fn player_entry(props: &Props) -> Html {
     let user = use_context::<User>().expect("no user ctx found");
     let is_current = user.id == props.player.user.id;
+    let is_player_admin = props.player.role == PlayerRole::Admin;
+    let is_observer = props.player.role == PlayerRole::Observer;
+    let now = use_state(browser_now);
+    {
+        let now = now.clone();
+        use_interval(move || now.set(browser_now()), 30_000);
+    }
+
+    let on_role_change = {
+        let player_id = props.player.user.id;
+        let role = if is_player_admin {
+            PlayerRole::Player
+        } else {
+            PlayerRole::Admin
+        };
+        let on_action = props.on_action.clone();
+        Callback::from(move |_| on_action.emit(GameAction::PlayerRoleChanged(player_id, role)))
+    };
+    let on_observe_change = {
+        let player_id = props.player.user.id;
+        let role = if is_observer {
+            PlayerRole::Player
+        } else {
+            PlayerRole::Observer
+        };
+        let on_action = props.on_action.clone();
+        Callback::from(move |_| on_action.emit(GameAction::PlayerRoleChanged(player_id, role)))
+    };
+    let on_transfer = {
+        let player_id = props.player.user.id;
+        let on_action = props.on_action.clone();
+        Callback::from(move |_| on_action.emit(GameAction::AdminTransferred(player_id)))
+    };
+
+    let button_class = classes!("ml-2", "text-xs", "hover:text-blue-400");
     html! {
         <li
             class={classes!(
                 "py-2", "px-4", "border-b",
+                "flex", "items-center",
                 "text-slate-500",
                 is_current.then_some("font-semibold"),
                 is_current.then_some("text-lg"),
             )}
         >
-            {&props.player.user.name}
-            if let PlayerRole::Admin = &props.player.role {
-                <span>{" (moderator)"}</span>
+            <span
+                class={classes!("mr-2", "w-2", "h-2", "rounded-full", presence_class(props.player.presence))}
+                title={presence_label(&props.player, *now)}
+            />
+            <span class="flex-1">
+                {&props.player.user.name}
+                if is_player_admin {
+                    <span>{" (moderator)"}</span>
+                }
+                if is_observer {
+                    <span>{" (observer)"}</span>
+                }
+                if props.player.presence != Presence::Online {
+                    <span class="ml-2 text-xs font-normal text-slate-400">
+                        {presence_label(&props.player, *now)}
+                    </span>
+                }
+            </span>
+            if is_current && !is_player_admin {
+                <button class={button_class.clone()} onclick={on_observe_change.clone()}>
+                    {if is_observer { "Join voting" } else { "Watch only" }}
+                </button>
+            }
+            if props.is_admin && !is_current {
+                if !is_player_admin {
+                    <button class={button_class.clone()} onclick={on_observe_change}>
+                        {if is_observer { "Make voter" } else { "Make observer" }}
+                    </button>
+                }
+                <button class={button_class.clone()} onclick={on_role_change}>
+                    {if is_player_admin { "Remove moderator" } else { "Make moderator" }}
+                </button>
+                <button class={button_class} onclick={on_transfer}>
+                    {"Hand over"}
+                </button>
             }
         </li>
     }
 }
+
+fn presence_class(presence: Presence) -> &'static str {
+    match presence {
+        Presence::Online => "bg-green-500",
+        Presence::Idle => "bg-yellow-500",
+        Presence::Away => "bg-orange-500",
+        Presence::Disconnected => "bg-slate-400",
+    }
+}
+
+fn presence_label(player: &Player, now: Timestamp) -> String {
+    let since = player
+        .last_seen
+        .map(|last_seen| format_elapsed(now.saturating_sub(last_seen)));
+    match (player.presence, since) {
+        (Presence::Online, _) => "online".to_string(),
+        (Presence::Idle, Some(since)) => format!("idle for {since}"),
+        (Presence::Away, Some(since)) => format!("away for {since}"),
+        (Presence::Disconnected, Some(since)) => format!("last seen {since} ago"),
+        (Presence::Idle, None) => "idle".to_string(),
+        (Presence::Away, None) => "away".to_string(),
+        (Presence::Disconnected, None) => "disconnected".to_string(),
+    }
+}
+
+fn format_elapsed(ms: Timestamp) -> String {
+    let minutes = ms / 60_000;
+    match minutes {
+        0 => "less than a minute".to_string(),
+        1..=59 => format!("{minutes} min"),
+        _ => format!("{} h", minutes / 60),
+    }
+}
@@ -1,12 +1,14 @@
-use common::{Player, UserId};
+use common::{GameAction, Player, UserId};
 use indexmap::IndexMap;
 use yew::prelude::*;
 
 use crate::components::player_entry::PlayerEntry;
 
-#[derive(Clone, PartialEq, Eq, Properties)]
+#[derive(Clone, PartialEq, Properties)]
 pub(crate) struct Props {
     pub(crate) players: IndexMap<UserId, Player>,
+    pub(crate) is_admin: bool,
+    pub(crate) on_action: Callback<GameAction>,
 }
 
 #[function_component(Players)]
@@ -17,8 +19,10 @@ pub(crate) fn players(props: &Props) -> Html {
         .map(|(user_id, player)| {
             let key = user_id.to_string();
             let player = player.clone();
+            let is_admin = props.is_admin;
+            let on_action = &props.on_action;
             html! {
-                <PlayerEntry {key} {player} />
+                <PlayerEntry {key} {player} {is_admin} {on_action} />
             }
         })
         .collect::<Html>();
@@ -0,0 +1,49 @@
+use common::{GameAction, Presence};
+use yew::prelude::*;
+use yew_hooks::{use_event_with_window, use_interval};
+
+/// Without any input for this long the player becomes idle.
+const IDLE_AFTER_MS: f64 = 2.0 * 60.0 * 1000.0;
+
+/// Reports to the game whether the player is around, the current presence is the one
+/// the game knows about, `None` before the player has joined.
+#[hook]
+pub(crate) fn use_presence_tracker(presence: Option<Presence>, on_action: &Callback<GameAction>) {
+    let last_input = use_mut_ref(js_sys::Date::now);
+    {
+        let last_input = last_input.clone();
+        use_event_with_window("mousemove", move |_: MouseEvent| {
+            *last_input.borrow_mut() = js_sys::Date::now();
+        });
+    }
+    {
+        let last_input = last_input.clone();
+        use_event_with_window("keydown", move |_: KeyboardEvent| {
+            *last_input.borrow_mut() = js_sys::Date::now();
+        });
+    }
+
+    let on_action = on_action.clone();
+    use_interval(
+        move || {
+            let Some(presence) = presence else {
+                return;
+            };
+            let is_hidden = web_sys::window()
+                .and_then(|window| window.document())
+                .map_or(false, |document| document.hidden());
+            let current = if is_hidden {
+                Presence::Away
+            } else if js_sys::Date::now() - *last_input.borrow() > IDLE_AFTER_MS {
+                Presence::Idle
+            } else {
+                Presence::Online
+            };
+            // the server sets the time
+            if current != presence {
+                on_action.emit(GameAction::PresenceChanged(current, 0));
+            }
+        },
+        5000,
+    );
+}
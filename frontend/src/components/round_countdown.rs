@@ -0,0 +1,51 @@
+use common::{RoundTimer, Timestamp};
+use yew::prelude::*;
+use yew_hooks::use_interval;
+
+#[derive(Clone, Debug, PartialEq, Eq, Properties)]
+pub(crate) struct Props {
+    pub(crate) timer: RoundTimer,
+    /// Milliseconds the clock of the server is ahead of the browser one.
+    pub(crate) clock_offset: i64,
+}
+
+#[function_component(RoundCountdown)]
+pub(crate) fn round_countdown(props: &Props) -> Html {
+    // the timer ends by the clock of the server, which the browser one may disagree with
+    let clock_offset = props.clock_offset;
+    let now = use_state(|| server_now(clock_offset));
+    {
+        let now = now.clone();
+        use_interval(move || now.set(server_now(clock_offset)), 250);
+    }
+
+    let remaining_secs = (props.timer.remaining_ms(*now) + 999) / 1000;
+    let is_ending = remaining_secs <= 10;
+
+    html! {
+        <h5
+            class={classes!(
+                "mx-2", "text-2xl", "font-bold",
+                is_ending.then_some("text-red-500"),
+                (!is_ending).then_some("text-slate-500"),
+            )}
+        >
+            {format!("{}:{:02}", remaining_secs / 60, remaining_secs % 60)}
+        </h5>
+    }
+}
+
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+pub(crate) fn browser_now() -> Timestamp {
+    js_sys::Date::now() as Timestamp
+}
+
+/// Milliseconds the clock of the server, reporting the given time, is ahead of the browser one.
+#[allow(clippy::cast_possible_wrap)]
+pub(crate) fn clock_offset(server_now: Timestamp) -> i64 {
+    server_now as i64 - browser_now() as i64
+}
+
+fn server_now(clock_offset: i64) -> Timestamp {
+    browser_now().saturating_add_signed(clock_offset)
+}
@@ -1,35 +1,47 @@
-use common::{GameAction, Player, PlayerRole, SelectedStory, UserId, Vote};
+use common::{Deck, GameAction, Player, PlayerRole, RoundTimer, SelectedStory, UserId, Vote};
 use indexmap::IndexMap;
 use web_sys::HtmlInputElement;
 use yew::prelude::*;
 
 use crate::components::{
     allowed_votes::AllowedVotes, button::Button, casted_vote_entry::CastedVoteEntry,
+    round_countdown::RoundCountdown, story_details::StoryDetails,
 };
 
+const TIMER_DURATIONS_SECS: [u64; 4] = [30, 60, 120, 300];
+
 #[derive(Clone, Debug, PartialEq, Properties)]
 pub(crate) struct Props {
     pub(crate) story: SelectedStory,
     pub(crate) user_id: UserId,
     pub(crate) players: IndexMap<UserId, Player>,
+    pub(crate) deck: Deck,
+    /// How many of the active voters have voted, out of how many.
+    pub(crate) progress: (usize, usize),
+    pub(crate) auto_reveal: bool,
+    /// Milliseconds the clock of the server is ahead of the browser one.
+    pub(crate) clock_offset: i64,
     pub(crate) on_action: Callback<GameAction>,
 }
 
 #[function_component(SelectedStoryEntry)]
 pub(crate) fn selected_story_entry(props: &Props) -> Html {
-    let final_estimate_handle = use_state(|| 0_i32);
+    let final_estimate_handle = use_state(|| None::<i32>);
+    let timer_secs_handle = use_state(|| TIMER_DURATIONS_SECS[1]);
+    let timer_reveal_handle = use_state(|| true);
     let votes = props
         .players
         .values()
-        .filter(|player| player.active)
+        .filter(|player| player.active && player.can_vote())
         .map(|player| {
             let key = player.user.id.to_string();
             let vote = props.story.votes.get(&player.user.id).copied();
             let is_revealed =
                 (props.story.votes_revealed || props.user_id == player.user.id) && vote.is_some();
             let player = player.clone();
+            let deck = props.deck;
             html! {
-                <CastedVoteEntry {key} {player} {is_revealed} {vote} />
+                <CastedVoteEntry {key} {player} {is_revealed} {vote} {deck} />
             }
         })
         .collect::<Html>();
@@ -41,13 +53,14 @@ pub(crate) fn selected_story_entry(props: &Props) -> Html {
 
     let on_accept_round = {
         let on_action = props.on_action.clone();
-        let estimate = Some(Vote::new(*final_estimate_handle).unwrap());
-        Callback::from(move |_| on_action.emit(GameAction::ResultsApproved(estimate)))
+        let estimate = final_estimate_handle.and_then(|value| Vote::new(value, &props.deck).ok());
+        // the server sets when the round ended
+        Callback::from(move |_| on_action.emit(GameAction::ResultsApproved(estimate, 0)))
     };
 
     let on_play_again = {
         let on_action = props.on_action.clone();
-        Callback::from(move |_| on_action.emit(GameAction::VotesCleared))
+        Callback::from(move |_| on_action.emit(GameAction::VotesCleared(0)))
     };
 
     let on_reveal_cards = {
@@ -55,67 +68,160 @@ pub(crate) fn selected_story_entry(props: &Props) -> Html {
         Callback::from(move |_| on_action.emit(GameAction::VotesRevealed))
     };
 
+    let on_auto_reveal_change = {
+        let on_action = props.on_action.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            on_action.emit(GameAction::AutoRevealChanged(input.checked()));
+        })
+    };
+
+    let on_start_timer = {
+        let on_action = props.on_action.clone();
+        // the server replaces the start time with its own
+        let timer = RoundTimer::new(0, *timer_secs_handle * 1000, *timer_reveal_handle);
+        Callback::from(move |_| on_action.emit(GameAction::TimerStarted(timer)))
+    };
+
+    let on_stop_timer = {
+        let on_action = props.on_action.clone();
+        Callback::from(move |_| on_action.emit(GameAction::TimerStopped))
+    };
+
+    let on_timer_secs_change = {
+        let timer_secs_handle = timer_secs_handle.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            if let Ok(secs) = input.value().parse() {
+                timer_secs_handle.set(secs);
+            }
+        })
+    };
+
+    let on_timer_reveal_change = {
+        let timer_reveal_handle = timer_reveal_handle.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            timer_reveal_handle.set(input.checked());
+        })
+    };
+
+    let timer_options = TIMER_DURATIONS_SECS
+        .iter()
+        .map(|secs| {
+            html! {
+                <option key={*secs} value={secs.to_string()} selected={*secs == *timer_secs_handle}>
+                    {format!("{secs}s")}
+                </option>
+            }
+        })
+        .collect::<Html>();
+
     let on_cancel_round = {
         let on_action = props.on_action.clone();
-        Callback::from(move |_| on_action.emit(GameAction::VotingClosed))
+        // the server sets when the round ended
+        Callback::from(move |_| on_action.emit(GameAction::VotingClosed(0)))
     };
 
     let is_admin = matches!(props.players.get(&props.user_id), Some(player) if player.role == PlayerRole::Admin);
+    let can_vote = matches!(props.players.get(&props.user_id), Some(player) if player.can_vote());
 
     let can_accept = props.story.can_accept();
     let can_play_again = props.story.can_play_again();
     let can_reveal = props.story.can_reveal();
-    let avrg = &props.story.votes_avrg();
-    let closest = Vote::get_closest_vote(avrg).value();
+    let avrg = props.story.votes_avrg();
+    let closest = avrg.map(|avrg| Vote::get_closest_vote(&avrg, &props.deck));
+    let closest_value = closest.and_then(|vote| vote.value());
 
     {
         let final_estimate_handle = final_estimate_handle.clone();
         use_effect_with_deps(
+            // nothing is picked when all the votes are special cards
             move |closest| {
                 final_estimate_handle.set(*closest);
                 || ()
             },
-            closest,
+            closest_value,
         );
     };
 
-    let options = Vote::get_allowed_votes()
+    let options = props
+        .deck
+        .values()
         .iter()
-        .map(|vote| {
-            let value = vote.value();
+        .map(|value| {
             html! {
                 <option
-                    key={value}
+                    key={*value}
                     value={value.to_string()}
-                    selected={ value == *final_estimate_handle }
+                    selected={ Some(*value) == *final_estimate_handle }
                 >
-                    {value}
+                    {props.deck.label(Vote::Points(*value))}
                 </option>
             }
         })
         .collect::<Html>();
 
+    // how many players picked each of the special cards
+    let special_votes = [
+        (Vote::Unsure, "not sure"),
+        (Vote::Break, "need a break"),
+        (Vote::TooBig, "too big"),
+    ]
+    .into_iter()
+    .map(|(vote, description)| (vote, description, props.story.count_votes(vote)))
+    .filter(|(_, _, count)| *count > 0)
+    .map(|(vote, description, count)| {
+        let label = props.deck.label(vote);
+        html! {
+            <span key={label.clone()} class="mr-4">
+                <b>{label}</b>
+                {format!(" ×{count} ({description})")}
+            </span>
+        }
+    })
+    .collect::<Vec<_>>();
+
     let onchange = {
         let final_estimate_handle = final_estimate_handle.clone();
         Callback::from(move |e: Event| {
             let input: HtmlInputElement = e.target_unchecked_into();
             let value = input.value();
-            let value: i32 = value.parse().unwrap();
-            final_estimate_handle.set(value);
+            final_estimate_handle.set(value.parse().ok());
         })
     };
 
     html! {
         <div class={classes!("mt-2", "mb-4")}>
-            <h4 class={classes!("font-bold", "text-2xl", "text-slate-500")}>
-                {&props.story.info.title}
-            </h4>
+            <div class="flex items-center justify-between">
+                <h4 class={classes!("font-bold", "text-2xl", "text-slate-500")}>
+                    {&props.story.info.title}
+                </h4>
+                if let Some(timer) = props.story.timer {
+                    <RoundCountdown {timer} clock_offset={props.clock_offset} />
+                }
+            </div>
+            <StoryDetails info={props.story.info.clone()} />
 
             <ul class={classes!("list-none", "my-4", "flex", "flex-wrap")}>
                 { votes }
             </ul>
 
-            <AllowedVotes {on_vote_click} />
+            if !props.story.votes_revealed {
+                <h5 class="mx-2 text-sm text-slate-500">
+                    {format!("{} of {} voted", props.progress.0, props.progress.1)}
+                </h5>
+            }
+
+            if props.story.votes_revealed && !special_votes.is_empty() {
+                <h5 class="mx-2 text-sm text-slate-500">
+                    {special_votes}
+                </h5>
+            }
+
+            if can_vote {
+                <AllowedVotes deck={props.deck} {on_vote_click} />
+            }
 
             if is_admin {
                 <>
@@ -124,25 +230,35 @@ pub(crate) fn selected_story_entry(props: &Props) -> Html {
                             <h5 class="text-sm mr-4">
                                 <span class="mr-2">
                                     {"Average: "}
-                                    <b>{avrg}</b>
+                                    <b>{avrg.map_or_else(|| "-".to_string(), |avrg| avrg.to_string())}</b>
                                 </span>
                                 <span class="mr-2">
                                     {"Closest: "}
-                                    <b>{closest}</b>
+                                    <b>{closest.map(|vote| props.deck.label(vote)).unwrap_or_default()}</b>
                                 </span>
                                 <span class="mr-2">
                                     {"Final: "}
-                                    <b>{*final_estimate_handle}</b>
+                                    <b>
+                                        {
+                                            final_estimate_handle
+                                                .and_then(|value| Vote::new(value, &props.deck).ok())
+                                                .map(|vote| props.deck.label(vote))
+                                                .unwrap_or_default()
+                                        }
+                                    </b>
                                 </span>
                             </h5>
                             <select class="py-1 px-2 text-sm bg-white rounded-sm shadow-sm" {onchange}>
+                                <option value="" selected={final_estimate_handle.is_none()} disabled=true>
+                                    {"Pick an estimate"}
+                                </option>
                                 {options}
                             </select>
                         </div>
                     }
                     <div class={classes!("list-none", "mt-6", "mb-12", "flex", "flex-wrap")}>
                         <div class="m-1">
-                            <Button disabled={!can_accept} onclick={on_accept_round}>{ "Accept round" }</Button>
+                            <Button disabled={!can_accept || final_estimate_handle.is_none()} onclick={on_accept_round}>{ "Accept round" }</Button>
                         </div>
                         <div class="m-1">
                             <Button disabled={!can_play_again} onclick={on_play_again}>{ "Play again" }</Button>
@@ -153,7 +269,44 @@ pub(crate) fn selected_story_entry(props: &Props) -> Html {
                         <div class="m-1">
                             <Button onclick={on_cancel_round}>{ "Cancel round" }</Button>
                         </div>
+                        <label class="m-1 flex items-center text-sm text-slate-500">
+                            <input
+                                class="mr-2"
+                                type="checkbox"
+                                checked={props.auto_reveal}
+                                onchange={on_auto_reveal_change}
+                            />
+                            {"Reveal when everyone voted"}
+                        </label>
                     </div>
+                    if !props.story.votes_revealed {
+                        <div class="mb-12 flex items-center text-sm text-slate-500">
+                            if props.story.timer.is_some() {
+                                <div class="m-1">
+                                    <Button onclick={on_stop_timer}>{ "Stop timer" }</Button>
+                                </div>
+                            } else {
+                                <select
+                                    class="m-1 py-1 px-2 bg-white rounded-sm shadow-sm"
+                                    onchange={on_timer_secs_change}
+                                >
+                                    {timer_options}
+                                </select>
+                                <label class="m-1 flex items-center">
+                                    <input
+                                        class="mr-2"
+                                        type="checkbox"
+                                        checked={*timer_reveal_handle}
+                                        onchange={on_timer_reveal_change}
+                                    />
+                                    {"Reveal when time is up"}
+                                </label>
+                                <div class="m-1">
+                                    <Button onclick={on_start_timer}>{ "Start timer" }</Button>
+                                </div>
+                            }
+                        </div>
+                    }
                 </>
             }
         </div>
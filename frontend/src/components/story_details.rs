@@ -0,0 +1,58 @@
+use common::{links::is_safe_url, StoryInfo};
+use yew::prelude::*;
+
+use crate::components::markdown::Markdown;
+
+#[derive(Clone, Debug, PartialEq, Eq, Properties)]
+pub(crate) struct Props {
+    pub(crate) info: StoryInfo,
+    #[prop_or(true)]
+    pub(crate) show_description: bool,
+}
+
+#[function_component(StoryDetails)]
+pub(crate) fn story_details(props: &Props) -> Html {
+    let labels = props
+        .info
+        .labels
+        .iter()
+        .map(|label| {
+            html! {
+                <span
+                    key={label.clone()}
+                    class="mr-1 py-0.5 px-2 text-xs rounded bg-slate-200 text-slate-600"
+                >
+                    {label}
+                </span>
+            }
+        })
+        .collect::<Html>();
+
+    html! {
+        <div>
+            <div class="flex flex-wrap items-center text-xs text-slate-500">
+                {labels}
+                if let Some(assignee) = &props.info.assignee {
+                    <span class="mr-2">{format!("Assignee: {assignee}")}</span>
+                }
+                if let Some(url) = props.info.url.as_ref().filter(|url| is_safe_url(url)) {
+                    <a
+                        class="text-blue-500 hover:text-blue-400 underline"
+                        href={url.clone()}
+                        target="_blank"
+                        rel="noopener noreferrer"
+                    >
+                        {props.info.key.clone().unwrap_or_else(|| "Open ticket".to_string())}
+                    </a>
+                } else if let Some(key) = &props.info.key {
+                    <span>{key}</span>
+                }
+            </div>
+            if let (true, Some(description)) = (props.show_description, &props.info.description) {
+                <div class="mt-2">
+                    <Markdown source={description.clone()} />
+                </div>
+            }
+        </div>
+    }
+}
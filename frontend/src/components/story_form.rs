@@ -1,4 +1,9 @@
-use common::{BacklogStory, GameAction, StoryInfo};
+use common::{
+    backlog_csv::{self, CsvColumns},
+    issue_import::{self, ImportError, ImportFormat},
+    BacklogStory, GameAction, StoryInfo,
+};
+use gloo_file::{futures::read_as_text, File};
 use web_sys::HtmlInputElement;
 use yew::prelude::*;
 
@@ -12,6 +17,7 @@ pub(crate) struct Props {
 #[function_component(StoryForm)]
 pub(crate) fn story_form(props: &Props) -> Html {
     let raw_form = use_state(String::new);
+    let import_error = use_state(|| None as Option<String>);
     let story_titles: Vec<String> = raw_form
         .split('\n')
         .map(|s| s.trim().to_string())
@@ -36,15 +42,44 @@ pub(crate) fn story_form(props: &Props) -> Html {
             raw_form.set(String::new());
             let stories = story_titles
                 .iter()
-                .map(|title| {
-                    BacklogStory::new(StoryInfo {
-                        title: title.clone(),
-                    })
-                })
+                .map(|title| BacklogStory::new(StoryInfo::new(title.clone())))
                 .collect();
             on_action.emit(GameAction::StoriesAdded(stories));
         })
     };
+    let on_file_change = {
+        let import_error = import_error.clone();
+        let on_action = props.on_action.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            let Some(file) = input.files().and_then(|files| files.get(0)) else {
+                return;
+            };
+            let is_csv = file.name().to_lowercase().ends_with(".csv");
+            // allow importing the same file again
+            input.set_value("");
+            let import_error = import_error.clone();
+            let on_action = on_action.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                let result = match read_as_text(&File::from(file)).await {
+                    Ok(data) if is_csv => backlog_csv::parse_stories(&data, &CsvColumns::default())
+                        .map_err(|err| err.to_string()),
+                    Ok(data) => ImportFormat::detect(&data)
+                        .ok_or(ImportError::UnknownFormat)
+                        .and_then(|format| issue_import::import_stories(format, &data))
+                        .map_err(|err| err.to_string()),
+                    Err(err) => Err(err.to_string()),
+                };
+                match result {
+                    Ok(stories) => {
+                        import_error.set(None);
+                        on_action.emit(GameAction::StoriesAdded(stories));
+                    }
+                    Err(err) => import_error.set(Some(err)),
+                }
+            });
+        })
+    };
 
     html! {
         <div class={classes!("mb-8")}>
@@ -55,9 +90,23 @@ pub(crate) fn story_form(props: &Props) -> Html {
                 {oninput}
             />
             <div class="h-4" />
-            <Button {onclick} {disabled}>
-                {"Add stories"}
-            </Button>
+            <div class="flex items-center">
+                <Button {onclick} {disabled}>
+                    {"Add stories"}
+                </Button>
+                <label class="ml-4 text-sm text-slate-500 cursor-pointer hover:text-blue-400">
+                    {"or import a CSV file, a Jira export or GitHub issues"}
+                    <input
+                        class="hidden"
+                        type="file"
+                        accept=".csv,.json,.xml"
+                        onchange={on_file_change}
+                    />
+                </label>
+            </div>
+            if let Some(error) = &*import_error {
+                <p class="mt-2 text-sm text-red-500">{format!("Import failed: {error}")}</p>
+            }
         </div>
     }
 }
@@ -0,0 +1,95 @@
+use common::StoryInfo;
+use web_sys::HtmlInputElement;
+use yew::prelude::*;
+
+use crate::components::{button::Button, form_input::FormInput, form_textarea::FormTextarea};
+
+#[derive(Clone, Debug, PartialEq, Properties)]
+pub(crate) struct Props {
+    pub(crate) info: StoryInfo,
+    pub(crate) on_submit: Callback<StoryInfo>,
+}
+
+#[function_component(StoryInfoForm)]
+pub(crate) fn story_info_form(props: &Props) -> Html {
+    let info = use_state(|| props.info.clone());
+    let labels = use_state(|| props.info.labels.join(", "));
+
+    // every optional field is cleared when left empty
+    let on_field_input = |update: fn(&mut StoryInfo, String)| {
+        let info = info.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            let mut new_info = (*info).clone();
+            update(&mut new_info, input.value());
+            info.set(new_info);
+        })
+    };
+    let on_title_input = on_field_input(|info, value| info.title = value);
+    let on_description_input = on_field_input(|info, value| info.description = non_empty(value));
+    let on_url_input = on_field_input(|info, value| info.url = non_empty(value));
+    let on_assignee_input = on_field_input(|info, value| info.assignee = non_empty(value));
+    let on_labels_input = {
+        let labels = labels.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            labels.set(input.value());
+        })
+    };
+
+    let title = info.title.trim().to_string();
+    let disabled = title.is_empty();
+    let onclick = {
+        let info = info.clone();
+        let labels = labels.clone();
+        let on_submit = props.on_submit.clone();
+        Callback::from(move |_| {
+            let labels = labels
+                .split(',')
+                .map(str::trim)
+                .filter(|label| !label.is_empty())
+                .map(ToString::to_string)
+                .collect();
+            on_submit.emit(StoryInfo {
+                title: title.clone(),
+                labels,
+                ..(*info).clone()
+            });
+        })
+    };
+
+    html! {
+        <div class="flex-1">
+            <FormInput label="Title" value={info.title.clone()} oninput={on_title_input} />
+            <FormTextarea
+                rows={4}
+                label="Description (markdown)"
+                value={info.description.clone().unwrap_or_default()}
+                oninput={on_description_input}
+            />
+            <FormInput
+                label="Ticket link"
+                value={info.url.clone().unwrap_or_default()}
+                oninput={on_url_input}
+            />
+            <FormInput
+                label="Labels (comma separated)"
+                value={(*labels).clone()}
+                oninput={on_labels_input}
+            />
+            <FormInput
+                label="Assignee"
+                value={info.assignee.clone().unwrap_or_default()}
+                oninput={on_assignee_input}
+            />
+            <div class="h-4" />
+            <Button {onclick} {disabled}>
+                {"Save story"}
+            </Button>
+        </div>
+    }
+}
+
+fn non_empty(value: String) -> Option<String> {
+    (!value.trim().is_empty()).then_some(value)
+}
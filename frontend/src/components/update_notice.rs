@@ -0,0 +1,39 @@
+use common::{VersionInfo, PROTOCOL_VERSION};
+use gloo_net::http::Request;
+use yew::prelude::*;
+use yew_hooks::{use_async_with_options, use_interval, UseAsyncOptions};
+
+/// How often the version of the server is checked, it changes only with a deployment.
+const CHECK_EVERY_MS: u32 = 5 * 60 * 1000;
+
+/// Asks to reload the page once the server runs a different build than the one that served it.
+#[function_component(UpdateNotice)]
+pub(crate) fn update_notice() -> Html {
+    let server = use_async_with_options(
+        async { fetch_version().await.ok_or(()) },
+        UseAsyncOptions::enable_auto(),
+    );
+    {
+        let server = server.clone();
+        use_interval(move || server.run(), CHECK_EVERY_MS);
+    }
+
+    let is_outdated = server.data.as_ref().map_or(false, |server| {
+        server.protocol_version != PROTOCOL_VERSION || server.version != env!("CARGO_PKG_VERSION")
+    });
+    if !is_outdated {
+        return html! {};
+    }
+    html! {
+        <p class="p-2 mb-4 rounded bg-amber-100 text-sm text-amber-800">
+            {"A new version of the app is available, "}
+            <a class="underline" href="">{"reload the page"}</a>
+            {" to keep playing."}
+        </p>
+    }
+}
+
+async fn fetch_version() -> Option<VersionInfo> {
+    let response = Request::get("/api/version").send().await.ok()?;
+    response.json::<VersionInfo>().await.ok()
+}
@@ -1,4 +1,5 @@
-use common::User;
+use common::{Deck, User};
+use web_sys::HtmlInputElement;
 use yew::prelude::*;
 use yew_router::prelude::*;
 
@@ -9,7 +10,8 @@ use crate::Route;
 #[function_component(Home)]
 pub fn home() -> Html {
     let user = use_context::<User>().expect("no user ctx found");
-    let create_game = use_crate_game_req(&user);
+    let deck = use_state(Deck::default);
+    let create_game = use_crate_game_req(&user, *deck);
     let onclick = {
         let create_game = create_game.clone();
         Callback::from(move |_| {
@@ -17,6 +19,32 @@ pub fn home() -> Html {
         })
     };
 
+    let options = Deck::ALL
+        .iter()
+        .enumerate()
+        .map(|(idx, option)| {
+            html! {
+                <option key={idx} value={idx.to_string()} selected={option == &*deck}>
+                    {option.name()}
+                </option>
+            }
+        })
+        .collect::<Html>();
+
+    let onchange = {
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            if let Some(option) = input
+                .value()
+                .parse()
+                .ok()
+                .and_then(|idx: usize| Deck::ALL.get(idx))
+            {
+                deck.set(*option);
+            }
+        })
+    };
+
     html! {
         <section class="flex justify-center items-center h-full">
             {if let Some(error) = &create_game.error {
@@ -35,9 +63,15 @@ pub fn home() -> Html {
                 }
             } else {
                 html! {
-                    <Button disabled={create_game.loading} {onclick}>
-                        { "Generate new game" }
-                    </Button>
+                    <div class="flex items-center">
+                        <label class="mr-2 text-sm text-slate-500">{"Deck"}</label>
+                        <select class="mr-4 py-2 px-2 text-sm bg-white rounded-sm shadow-sm" {onchange}>
+                            {options}
+                        </select>
+                        <Button disabled={create_game.loading} {onclick}>
+                            { "Generate new game" }
+                        </Button>
+                    </div>
                 }
             }}
         </section>
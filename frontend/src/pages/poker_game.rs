@@ -1,4 +1,4 @@
-use common::{AppEvent, Game, GameId, User};
+use common::{report::GameSummary, AppEvent, ErrorCode, Game, GameId, User};
 use std::rc::Rc;
 use yew::prelude::*;
 use yew_hooks::UseWebSocketReadyState;
@@ -8,7 +8,8 @@ use crate::{
     components::{
         backlog_stories::BacklogStories, connection_indicator::ConnectionIndicator,
         connection_provider::use_game_connection, estimated_stories::EstimatedStories,
-        players::Players, selected_story_entry::SelectedStoryEntry, story_form::StoryForm,
+        players::Players, presence_tracker::use_presence_tracker,
+        selected_story_entry::SelectedStoryEntry, story_form::StoryForm,
     },
     Route,
 };
@@ -35,18 +36,31 @@ impl Reducible for GameState {
                 AppEvent::CurrentState(game) => GameState::Playing(game),
                 AppEvent::GameNotFound(_) => GameState::NotFound,
                 // TODO: this shouldn't happen, so figure out how to handle it
-                AppEvent::GameMessage(_, _) => GameState::Loading,
+                AppEvent::Hello(_)
+                | AppEvent::ServerTime(_)
+                | AppEvent::GameMessage(_, _)
+                | AppEvent::GameUpdate(_, _, _)
+                | AppEvent::ResyncRequested
+                | AppEvent::JoinGame(_, _)
+                | AppEvent::SessionStarted(_)
+                | AppEvent::SessionRejected
+                | AppEvent::Error { .. } => GameState::Loading,
             },
-            GameState::Playing(game) => {
-                let mut game = game.clone();
-                match message {
-                    AppEvent::GameMessage(user_id, action) => {
-                        game.update(user_id, action);
-                        GameState::Playing(game)
+            GameState::Playing(game) => match message {
+                // sent on reconnection and as a reply to a resync
+                AppEvent::CurrentState(game) => GameState::Playing(game),
+                // updates out of order are skipped, a gap is detected before dispatching
+                AppEvent::GameUpdate(seq, user_id, action) if seq == game.seq + 1 => {
+                    let mut game = game.clone();
+                    if let Err(err) = game.update(user_id, action) {
+                        log::warn!("failed to apply action of {:?}: {}", user_id, err);
                     }
-                    _ => GameState::Playing(game),
+                    GameState::Playing(game)
                 }
-            }
+                // the game was removed while the page was open, e.g. reaped after a long absence
+                AppEvent::GameNotFound(_) => GameState::NotFound,
+                _ => return self,
+            },
             GameState::NotFound => GameState::NotFound,
         }
         .into()
@@ -66,15 +80,28 @@ pub(crate) fn poker_game(props: &Props) -> Html {
         use_effect_with_deps(
             move |message| {
                 if let Some(message) = message {
-                    let action = serde_json::from_str(message).unwrap();
-                    state.dispatch(action);
+                    match serde_json::from_str(message) {
+                        Ok(AppEvent::GameUpdate(seq, _, _)) if matches!(&*state, GameState::Playing(game) if seq > game.seq + 1) =>
+                        {
+                            log::warn!("missed updates before {}, resyncing", seq);
+                            ws.resync.emit(());
+                        }
+                        Ok(event) => state.dispatch(event),
+                        Err(err) => log::error!("invalid message from server: {}", err),
+                    }
                 }
                 || ()
             },
-            ws.message,
+            ws.message.clone(),
         );
     }
 
+    let presence = match &*state {
+        GameState::Playing(game) => game.players.get(&user.id).map(|player| player.presence),
+        GameState::Loading | GameState::NotFound => None,
+    };
+    use_presence_tracker(presence, &conn.send);
+
     match &*state {
         GameState::Loading => html! {
             <section class="h-full flex items-center justify-center">
@@ -96,25 +123,63 @@ pub(crate) fn poker_game(props: &Props) -> Html {
                 UseWebSocketReadyState::Closed => ("Connection closed", "bg-red-500"),
             };
 
+            let error = conn.error.as_ref().map(|(code, message)| match code {
+                ErrorCode::UnsupportedProtocol => {
+                    "The game was updated, please reload the page.".to_string()
+                }
+                _ => message.clone(),
+            });
+
             html! {
                 <>
                     <ConnectionIndicator {label} {bg_class} />
+                    if let Some(error) = error {
+                        <div class="max-w-7xl mx-auto px-4 pt-4">
+                            <p class="p-2 rounded bg-red-100 text-sm text-red-700">{error}</p>
+                        </div>
+                    }
                     <div class="flex max-w-7xl mx-auto">
                         <section class="w-2/3 p-4">
 
                             <EstimatedStories
                                 stories={game.estimated_stories.clone()}
+                                players={game.players.clone()}
+                                deck={game.deck}
                             />
 
+                            if !game.estimated_stories.is_empty() {
+                                <div class="-mt-8 mb-12 flex justify-end text-sm text-slate-500">
+                                    <a
+                                        class="ml-4 hover:text-blue-400"
+                                        href={summary_url(game)}
+                                        download="summary.md"
+                                    >
+                                        {"Download summary"}
+                                    </a>
+                                    <a
+                                        class="ml-4 hover:text-blue-400"
+                                        href={format!("/api/game/{}/export.csv", game.id)}
+                                        download="estimates.csv"
+                                    >
+                                        {"Export estimates (CSV)"}
+                                    </a>
+                                </div>
+                            }
+
                             {
                                 if let Some(story) = &game.selected_story {
                                     let key = story.id.to_string();
                                     let story = story.clone();
                                     let user_id = user.id;
                                     let players = game.players.clone();
+                                    let deck = game.deck;
+                                    let progress = game.voting_progress().unwrap_or_default();
+                                    let auto_reveal = game.auto_reveal;
+                                    let clock_offset = conn.clock_offset;
                                     html! {
                                         <SelectedStoryEntry
-                                            {key} {story} {user_id} {players}
+                                            {key} {story} {user_id} {players} {deck}
+                                            {progress} {auto_reveal} {clock_offset}
                                             on_action={&conn.send}
                                         />
                                     }
@@ -142,7 +207,7 @@ pub(crate) fn poker_game(props: &Props) -> Html {
                         </section>
                         <aside class="w-1/3 p-4">
 
-                            <Players {players} />
+                            <Players {players} {is_admin} on_action={&conn.send} />
 
                         </aside>
                     </div>
@@ -151,3 +216,12 @@ pub(crate) fn poker_game(props: &Props) -> Html {
         }
     }
 }
+
+// the summary is generated locally so it matches what the user sees
+fn summary_url(game: &Game) -> String {
+    let markdown = GameSummary::new(game).to_markdown();
+    format!(
+        "data:text/markdown;charset=utf-8,{}",
+        js_sys::encode_uri_component(&markdown)
+    )
+}